@@ -20,10 +20,10 @@ fn main() {
     let pkg_version = std::env::var("CARGO_PKG_VERSION").unwrap_or_else(|_| "0.0.0".to_string());
     let tag = run_git(&["describe", "--tags", "--exact-match"]);
     let sha = run_git(&["rev-parse", "--short", "HEAD"]);
-    let dirty = run_git(&["status", "--porcelain"]).map_or(false, |s| !s.is_empty());
+    let dirty = run_git(&["status", "--porcelain"]).is_some_and(|s| !s.is_empty());
 
-    let version = if tag.is_some() && !dirty {
-        tag.unwrap()
+    let version = if let (Some(tag), false) = (tag, dirty) {
+        tag
     } else if dirty {
         match sha {
             Some(sha) => format!("{pkg_version} ({sha}-dirty)"),
@@ -37,4 +37,7 @@ fn main() {
     };
 
     println!("cargo:rustc-env=M2IO_VERSION={version}");
+    if let Some(sha) = run_git(&["rev-parse", "HEAD"]) {
+        println!("cargo:rustc-env=M2IO_GIT_SHA={sha}");
+    }
 }
@@ -0,0 +1,567 @@
+//! Self-contained `.mmio` archives.
+//!
+//! An archive is a tar file holding the MMIO manifest as `mmio.json`, every
+//! modality payload as `payloads/<modality_said>` and every bundle the
+//! modalities reference as `bundles/<SAID>.json`, in its published JSON
+//! form. Entries are written in a fixed order with fixed metadata, so the
+//! same archive content always gives the same bytes.
+//!
+//! Payloads such as images and recordings can be large, so an archive only
+//! keeps where each payload is read from: memory, a file, or an entry of an
+//! archive on disk. Payloads are streamed from there and hashed as they are
+//! copied.
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use oca_sdk_rs::{OCABundle, OCABundleModel};
+use said::SelfAddressingIdentifier;
+
+use crate::semantics::fill_overlay_defs;
+use crate::{
+    parse_oca_bundle, parse_said, verify_bundle, BlobStore, BundleResolver, MmioError,
+    PayloadReader, Semantic, MMIO,
+};
+
+const MANIFEST: &str = "mmio.json";
+const PAYLOADS: &str = "payloads/";
+const BUNDLES: &str = "bundles/";
+
+/// Where the bytes of a payload are read from.
+#[derive(Debug, Clone)]
+enum PayloadSource {
+    Memory(Vec<u8>),
+    /// `size` bytes of the file at `path` from `offset`: a payload file or
+    /// an entry of an archive on disk.
+    File {
+        path: PathBuf,
+        offset: u64,
+        size: u64,
+    },
+}
+
+impl PayloadSource {
+    fn size(&self) -> u64 {
+        match self {
+            PayloadSource::Memory(payload) => payload.len() as u64,
+            PayloadSource::File { size, .. } => *size,
+        }
+    }
+
+    fn open(&self) -> Result<Box<dyn Read + '_>, MmioError> {
+        match self {
+            PayloadSource::Memory(payload) => Ok(Box::new(payload.as_slice())),
+            PayloadSource::File { path, offset, size } => {
+                let mut file = File::open(path)?;
+                file.seek(SeekFrom::Start(*offset))?;
+                Ok(Box::new(BufReader::new(file.take(*size))))
+            }
+        }
+    }
+}
+
+/// An MMIO together with its payloads and the bundles it references.
+///
+/// Payloads and bundles are checked against their SAIDs when added, when
+/// an archive is read and whenever a payload is copied, and an archive is
+/// only written or read if it is complete.
+#[derive(Debug, Clone)]
+pub struct MmioArchive {
+    pub mmio: MMIO,
+    payloads: HashMap<SelfAddressingIdentifier, PayloadSource>,
+    bundles: HashMap<SelfAddressingIdentifier, OCABundleModel>,
+}
+
+impl MmioArchive {
+    /// Start an archive of `mmio`, which must pass [`MMIO::verify`].
+    pub fn new(mmio: MMIO) -> Result<Self, MmioError> {
+        mmio.verify()?.into_result()?;
+        Ok(Self {
+            mmio,
+            payloads: HashMap::new(),
+            bundles: HashMap::new(),
+        })
+    }
+
+    /// The `modality_said` of the modality at `index`.
+    fn payload_said(&self, index: usize) -> Result<SelfAddressingIdentifier, MmioError> {
+        let modality =
+            self.mmio.modalities.get(index).ok_or_else(|| {
+                MmioError::InvalidModality(format!("no modality at index {}", index))
+            })?;
+        modality.modality_said.clone().ok_or_else(|| {
+            MmioError::InvalidModality(format!("modality {} has no modality_said", index))
+        })
+    }
+
+    /// Check `source` against the payload SAID of the modality at `index`
+    /// and add it.
+    fn add_source(&mut self, index: usize, source: PayloadSource) -> Result<(), MmioError> {
+        let said = self.payload_said(index)?;
+        PayloadReader::new(source.open()?, said.clone()).copy_to(&mut std::io::sink())?;
+        self.payloads.insert(said, source);
+        Ok(())
+    }
+
+    /// Add the payload of the modality at `index`, checking it against the
+    /// modality's `modality_said`. The payload is kept in memory, prefer
+    /// [`MmioArchive::add_payload_file`] for large payloads.
+    pub fn add_payload(&mut self, index: usize, payload: Vec<u8>) -> Result<(), MmioError> {
+        self.add_source(index, PayloadSource::Memory(payload))
+    }
+
+    /// Add the file at `path` as the payload of the modality at `index`.
+    /// The file is hashed now and read again when the archive is written.
+    pub fn add_payload_file(&mut self, index: usize, path: &Path) -> Result<(), MmioError> {
+        let source = PayloadSource::File {
+            path: path.to_path_buf(),
+            offset: 0,
+            size: fs::metadata(path)?.len(),
+        };
+        self.add_source(index, source)
+    }
+
+    /// Add the payload of the modality at `index` from `store`.
+    pub fn add_stored_payload(&mut self, index: usize, store: &BlobStore) -> Result<(), MmioError> {
+        let said = self.payload_said(index)?;
+        if !store.has(&said) {
+            return Err(MmioError::InvalidArchive(format!(
+                "payload {} of modality {} is not in the store",
+                said, index
+            )));
+        }
+        self.add_payload_file(index, &store.path(&said))
+    }
+
+    /// Verify `bundle` and add it to the archive.
+    pub fn add_bundle(
+        &mut self,
+        bundle: OCABundleModel,
+    ) -> Result<SelfAddressingIdentifier, MmioError> {
+        let said = verify_bundle(&bundle)?;
+        self.bundles.insert(said.clone(), bundle);
+        Ok(said)
+    }
+
+    /// Add the bundles of all referenced semantics not yet in the archive,
+    /// as found by `resolver`.
+    pub fn add_referenced_bundles(
+        &mut self,
+        resolver: &dyn BundleResolver,
+    ) -> Result<(), MmioError> {
+        for said in self.missing_bundles() {
+            let bundle = resolver
+                .resolve(&said)?
+                .ok_or_else(|| MmioError::InvalidSemantics(format!("bundle {} not found", said)))?;
+            self.add_bundle(bundle)?;
+        }
+        Ok(())
+    }
+
+    /// Open the payload with SAID `said`, or `None` if it is not in the
+    /// archive. The content is checked against the SAID as it is read.
+    pub fn payload(
+        &self,
+        said: &SelfAddressingIdentifier,
+    ) -> Result<Option<PayloadReader<'_>>, MmioError> {
+        self.payloads
+            .get(said)
+            .map(|source| Ok(PayloadReader::new(source.open()?, said.clone())))
+            .transpose()
+    }
+
+    /// The bundle with SAID `said`.
+    pub fn bundle(&self, said: &SelfAddressingIdentifier) -> Option<&OCABundleModel> {
+        self.bundles.get(said)
+    }
+
+    fn missing_bundles(&self) -> Vec<SelfAddressingIdentifier> {
+        let mut missing: Vec<SelfAddressingIdentifier> = vec![];
+        for modality in &self.mmio.modalities {
+            if let Semantic::Reference(said) = &modality.oca_bundle {
+                if !self.bundles.contains_key(said) && !missing.contains(said) {
+                    missing.push(said.clone());
+                }
+            }
+        }
+        missing
+    }
+
+    /// Check that every `modality_said` has its payload and every
+    /// referenced bundle is included, and that nothing else is.
+    fn check_complete(&self) -> Result<(), MmioError> {
+        let modalities = &self.mmio.modalities;
+        for (index, modality) in modalities.iter().enumerate() {
+            if let Some(said) = &modality.modality_said {
+                if !self.payloads.contains_key(said) {
+                    return Err(MmioError::InvalidArchive(format!(
+                        "payload {} of modality {} is missing",
+                        said, index
+                    )));
+                }
+            }
+        }
+        if let Some(said) = self.missing_bundles().first() {
+            return Err(MmioError::InvalidArchive(format!(
+                "referenced bundle {} is missing",
+                said
+            )));
+        }
+        if let Some(said) = self.payloads.keys().find(|s| {
+            !modalities
+                .iter()
+                .any(|m| m.modality_said.as_ref() == Some(*s))
+        }) {
+            return Err(MmioError::InvalidArchive(format!(
+                "payload {} belongs to no modality",
+                said
+            )));
+        }
+        if let Some(said) = self.bundles.keys().find(|s| {
+            !modalities
+                .iter()
+                .any(|m| matches!(&m.oca_bundle, Semantic::Reference(r) if r == *s))
+        }) {
+            return Err(MmioError::InvalidArchive(format!(
+                "bundle {} is not referenced by any modality",
+                said
+            )));
+        }
+        Ok(())
+    }
+
+    /// Payloads sorted by SAID, in the order they are written.
+    fn sorted_payloads(&self) -> Vec<(&SelfAddressingIdentifier, &PayloadSource)> {
+        let mut payloads: Vec<_> = self.payloads.iter().collect();
+        payloads.sort_by_key(|(said, _)| said.to_string());
+        payloads
+    }
+
+    /// Entries other than payloads, which are small enough to be held in
+    /// memory: the manifest, then the bundles sorted by SAID.
+    fn metadata_entries(&self) -> Result<Vec<(String, Vec<u8>)>, MmioError> {
+        let mut entries = vec![(MANIFEST.to_string(), serde_json::to_vec_pretty(&self.mmio)?)];
+        let mut bundles: Vec<_> = self.bundles.iter().collect();
+        bundles.sort_by_key(|(said, _)| said.to_string());
+        for (said, bundle) in bundles {
+            let mut published = bundle.clone();
+            fill_overlay_defs(&mut published)?;
+            let json = serde_json::to_vec(&OCABundle::from(published))?;
+            entries.push((format!("{}{}.json", BUNDLES, said), json));
+        }
+        Ok(entries)
+    }
+
+    /// Write the archive to `writer`. Payloads are streamed from their
+    /// sources and checked against their SAIDs as they are written.
+    pub fn write<W: Write>(&self, writer: W) -> Result<(), MmioError> {
+        self.check_complete()?;
+        let mut builder = tar::Builder::new(writer);
+        let mut metadata = self.metadata_entries()?.into_iter();
+        let (path, manifest) = metadata.next().expect("the manifest comes first");
+        append(
+            &mut builder,
+            &path,
+            manifest.len() as u64,
+            manifest.as_slice(),
+        )?;
+        for (said, source) in self.sorted_payloads() {
+            let mut reader = PayloadReader::new(source.open()?, said.clone());
+            let path = format!("{}{}", PAYLOADS, said);
+            append(&mut builder, &path, source.size(), &mut reader)?;
+        }
+        for (path, data) in metadata {
+            append(&mut builder, &path, data.len() as u64, data.as_slice())?;
+        }
+        builder.into_inner()?.flush()?;
+        Ok(())
+    }
+
+    /// Write the archive to the file at `path`.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), MmioError> {
+        self.write(BufWriter::new(File::create(path)?))
+    }
+
+    /// Read the archive at `path`, verifying the manifest, every payload
+    /// and every bundle against its SAID. Payloads are hashed as they are
+    /// read and stay in the file, from where [`MmioArchive::payload`]
+    /// streams them.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, MmioError> {
+        let path = path.as_ref();
+        Self::read_entries(BufReader::new(File::open(path)?), |said, offset, entry| {
+            let size = entry.size();
+            PayloadReader::new(Box::new(entry), said.clone()).copy_to(&mut std::io::sink())?;
+            Ok(PayloadSource::File {
+                path: path.to_path_buf(),
+                offset,
+                size,
+            })
+        })
+    }
+
+    /// Extract the archive read from `reader` into `dir`, with the same
+    /// layout as in the archive, verifying everything as [`MmioArchive::open`]
+    /// does. Payloads are hashed as they are copied to their files; a
+    /// payload that does not match its SAID is removed again.
+    pub fn extract<R: Read>(reader: R, dir: impl AsRef<Path>) -> Result<Self, MmioError> {
+        let dir = dir.as_ref();
+        fs::create_dir_all(dir.join(PAYLOADS))?;
+        let archive = Self::read_entries(reader, |said, _, entry| {
+            let path = dir.join(format!("{}{}", PAYLOADS, said));
+            let size = entry.size();
+            let mut file = BufWriter::new(File::create(&path)?);
+            let copied = PayloadReader::new(Box::new(entry), said.clone())
+                .copy_to(&mut file)
+                .and_then(|_| Ok(file.flush()?));
+            if let Err(e) = copied {
+                let _ = fs::remove_file(&path);
+                return Err(e);
+            }
+            Ok(PayloadSource::File {
+                path,
+                offset: 0,
+                size,
+            })
+        })?;
+        for (path, data) in archive.metadata_entries()? {
+            let path = dir.join(path);
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(path, data)?;
+        }
+        Ok(archive)
+    }
+
+    /// Read the entries of an archive, handing each payload entry with its
+    /// SAID and offset in the archive to `payload`, which verifies it and
+    /// says where it can be read from afterwards.
+    fn read_entries<R: Read>(
+        reader: R,
+        mut payload: impl FnMut(
+            &SelfAddressingIdentifier,
+            u64,
+            tar::Entry<'_, R>,
+        ) -> Result<PayloadSource, MmioError>,
+    ) -> Result<Self, MmioError> {
+        let mut mmio = None;
+        let mut payloads = vec![];
+        let mut bundles = vec![];
+        let mut archive = tar::Archive::new(reader);
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            if entry.header().entry_type().is_dir() {
+                continue;
+            }
+            let path = entry.path()?.to_string_lossy().into_owned();
+            if let Some(name) = path.strip_prefix(PAYLOADS) {
+                let said = parse_said(name)?;
+                let offset = entry.raw_file_position();
+                payloads.push((said.clone(), payload(&said, offset, entry)?));
+                continue;
+            }
+            let mut data = vec![];
+            entry.read_to_end(&mut data)?;
+            if path == MANIFEST {
+                mmio = Some(MMIO::from_json(&String::from_utf8_lossy(&data))?);
+            } else if let Some(name) = path
+                .strip_prefix(BUNDLES)
+                .and_then(|n| n.strip_suffix(".json"))
+            {
+                let expected = parse_said(name)?;
+                let bundle = parse_oca_bundle(&String::from_utf8_lossy(&data))?;
+                let actual = verify_bundle(&bundle)?;
+                if actual != expected {
+                    return Err(MmioError::SaidMismatch { expected, actual });
+                }
+                bundles.push((expected, bundle));
+            } else {
+                return Err(MmioError::InvalidArchive(format!(
+                    "unexpected archive entry '{}'",
+                    path
+                )));
+            }
+        }
+        let mmio = mmio
+            .ok_or_else(|| MmioError::InvalidArchive(format!("the archive has no {}", MANIFEST)))?;
+        let mut archive = MmioArchive::new(mmio)?;
+        archive.payloads.extend(payloads);
+        archive.bundles.extend(bundles);
+        archive.check_complete()?;
+        Ok(archive)
+    }
+
+    /// Write the entries of the archive as files under `dir`, with the
+    /// same layout as in the archive.
+    pub fn unpack(&self, dir: impl AsRef<Path>) -> Result<(), MmioError> {
+        self.check_complete()?;
+        let dir = dir.as_ref();
+        fs::create_dir_all(dir.join(PAYLOADS))?;
+        for (said, source) in self.sorted_payloads() {
+            let mut file = BufWriter::new(File::create(dir.join(format!("{}{}", PAYLOADS, said)))?);
+            PayloadReader::new(source.open()?, said.clone()).copy_to(&mut file)?;
+            file.flush()?;
+        }
+        for (path, data) in self.metadata_entries()? {
+            let path = dir.join(path);
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(path, data)?;
+        }
+        Ok(())
+    }
+}
+
+/// Append an entry of `size` bytes read from `data`, with fixed metadata.
+fn append<W: Write>(
+    builder: &mut tar::Builder<W>,
+    path: &str,
+    size: u64,
+    data: impl Read,
+) -> Result<(), MmioError> {
+    let mut header = tar::Header::new_ustar();
+    header.set_size(size);
+    header.set_mode(0o644);
+    header.set_mtime(0);
+    header.set_cksum();
+    builder
+        .append_data(&mut header, path, data)
+        .map_err(crate::locator::verified_error)
+}
+
+impl BundleResolver for MmioArchive {
+    fn resolve(
+        &self,
+        said: &SelfAddressingIdentifier,
+    ) -> Result<Option<OCABundleModel>, MmioError> {
+        Ok(self.bundles.get(said).cloned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{compile_ocafile, BundleStore, Modality};
+
+    #[test]
+    fn test_archive() {
+        let fake = compile_ocafile(include_str!("../docs/examples/assets/fake.ocafile")).unwrap();
+        let fake_said = fake.digest.clone().unwrap();
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let store = BundleStore::open(dir.join("bundles")).unwrap();
+        store.add(&fake).unwrap();
+
+        let payloads: [&[u8]; 2] = [b"name,surname\nJan,Kowalski\n", b"\x89PNG\r\n\x1a\nscan"];
+        let mut mmio = MMIO::new();
+        for (payload, semantic) in payloads.iter().zip([
+            Semantic::Reference(fake_said.clone()),
+            Semantic::Bundle(fake.clone()),
+        ]) {
+            mmio.add_modality(
+                Modality::from_reader(*payload, semantic, &Default::default()).unwrap(),
+            )
+            .unwrap();
+        }
+        mmio.compute_digest().unwrap();
+
+        let mut archive = MmioArchive::new(mmio.clone()).unwrap();
+        archive.add_payload(0, payloads[0].to_vec()).unwrap();
+        assert!(matches!(
+            archive.add_payload(1, payloads[0].to_vec()),
+            Err(MmioError::SaidMismatch { .. })
+        ));
+        // Incomplete archives are not written
+        assert!(matches!(
+            archive.write(vec![]),
+            Err(MmioError::InvalidArchive(_))
+        ));
+        // Payload files are streamed when the archive is written
+        let scan = dir.join("scan.png");
+        std::fs::write(&scan, payloads[1]).unwrap();
+        archive.add_payload_file(1, &scan).unwrap();
+        assert!(matches!(
+            archive.write(vec![]),
+            Err(MmioError::InvalidArchive(_))
+        ));
+        archive.add_referenced_bundles(&store).unwrap();
+
+        let mut packed = vec![];
+        archive.write(&mut packed).unwrap();
+        let mut again = vec![];
+        archive.write(&mut again).unwrap();
+        assert_eq!(packed, again);
+        // A payload file changed after it was added is not archived
+        std::fs::write(&scan, b"changed").unwrap();
+        assert!(matches!(
+            archive.write(vec![]),
+            Err(MmioError::SaidMismatch { .. })
+        ));
+
+        let path = dir.join("visits.mmio");
+        std::fs::write(&path, &packed).unwrap();
+        let read = MmioArchive::open(&path).unwrap();
+        assert_eq!(read.mmio.digest, mmio.digest);
+        let said = mmio.modalities[1].modality_said.as_ref().unwrap();
+        let payload = read.payload(said).unwrap().unwrap().read_verified();
+        assert_eq!(payload.unwrap(), payloads[1]);
+        assert_eq!(read.bundle(&fake_said).unwrap().digest, fake.digest);
+        let mut resolved = read.mmio.clone();
+        assert_eq!(resolved.resolve_semantics(&read).unwrap(), 1);
+        let mut rewritten = vec![];
+        read.write(&mut rewritten).unwrap();
+        assert_eq!(rewritten, packed);
+
+        read.unpack(dir.join("unpacked")).unwrap();
+        let extracted = MmioArchive::extract(packed.as_slice(), dir.join("extracted")).unwrap();
+        assert_eq!(extracted.mmio.digest, mmio.digest);
+        for unpacked in [dir.join("unpacked"), dir.join("extracted")] {
+            let report =
+                MMIO::from_json(&std::fs::read_to_string(unpacked.join("mmio.json")).unwrap())
+                    .unwrap()
+                    .verify_payloads_in_dir(&unpacked.join("payloads"))
+                    .unwrap();
+            assert!(report.is_valid());
+            assert!(unpacked
+                .join(format!("bundles/{}.json", fake_said))
+                .is_file());
+        }
+
+        // A payload changed inside the archive fails verification on read
+        let offset = packed.windows(8).position(|w| w == b"Kowalski").unwrap();
+        let mut tampered = packed.clone();
+        tampered[offset] = b'k';
+        std::fs::write(&path, &tampered).unwrap();
+        assert!(matches!(
+            MmioArchive::open(&path),
+            Err(MmioError::SaidMismatch { .. })
+        ));
+        let extracted = dir.join("tampered");
+        assert!(matches!(
+            MmioArchive::extract(tampered.as_slice(), &extracted),
+            Err(MmioError::SaidMismatch { .. })
+        ));
+        assert_eq!(
+            std::fs::read_dir(extracted.join("payloads"))
+                .unwrap()
+                .count(),
+            0
+        );
+
+        // Entries outside the layout are rejected
+        let mut builder = tar::Builder::new(vec![]);
+        let mut header = tar::Header::new_ustar();
+        header.set_size(2);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, "notes.txt", b"hi".as_slice())
+            .unwrap();
+        assert!(matches!(
+            MmioArchive::extract(builder.into_inner().unwrap().as_slice(), &extracted),
+            Err(MmioError::InvalidArchive(_))
+        ));
+    }
+}
@@ -1,6 +1,10 @@
 use clap::{ArgAction, Parser, Subcommand};
-use m2io_tmp::{Modality, ModalityType, Semantic, MMIO};
-use said::derivation::{HashFunction, HashFunctionCode};
+use m2io_tmp::{
+    hash_payload, parse_hash_code, BlobStore, BundleStore, Format, Link, MmioArchive, Modality,
+    ModalityOptions, ModalityType, PayloadStatus, ProvFormat, ProvenanceLog, Semantic,
+    DEFAULT_BUNDLE_STORE, FORMAT_NAMES, HASH_NAMES, MMIO, MMIO_VERSION,
+};
+use said::derivation::HashFunctionCode;
 use said::SelfAddressingIdentifier;
 use std::fs::{self, File};
 use std::io::Read;
@@ -15,7 +19,7 @@ const VERSION: &str = env!("M2IO_VERSION");
 #[command(disable_version_flag = true)]
 struct Cli {
     #[arg(short = 'v', long = "version", action = ArgAction::Version, help = "Print version")]
-    version: bool,
+    version: Option<bool>,
 
     #[command(subcommand)]
     command: Commands,
@@ -28,12 +32,22 @@ enum Commands {
         #[arg(short = 'm', long = "modalities",
             value_parser = parse_modality,
             num_args = 0..,
-            help = "Specify a modality using: file=path,bundle_said=<SAID>. Repeat for multiple modalities."
+            help = "Specify a modality using: file=path,bundle_said=<SAID>[,hash=<ALGORITHM>] or file=path,ocafile=path to embed the bundle compiled from an OCAfile. Repeat for multiple modalities."
         )]
-        modalities: Vec<Modality>,
+        modalities: Vec<ModalitySpec>,
 
         #[arg(short, long)]
         output: PathBuf,
+
+        #[arg(long = "hash", value_parser = parse_hash, default_value = "blake3-256",
+            help = hash_help()
+        )]
+        hash: HashFunctionCode,
+
+        #[arg(long = "format", value_parser = parse_format, default_value = "json",
+            help = format_help()
+        )]
+        format: Format,
     },
     Parse {
         #[arg(long = "mmio")]
@@ -42,147 +56,695 @@ enum Commands {
     Said {
         #[arg(long = "file")]
         file: PathBuf,
+
+        #[arg(long = "hash", value_parser = parse_hash, default_value = "blake3-256",
+            help = hash_help()
+        )]
+        hash: HashFunctionCode,
+    },
+    /// Verify modality payloads on disk against their modality_said
+    VerifyPayloads {
+        #[arg(long = "mmio")]
+        mmio: PathBuf,
+
+        #[arg(
+            long = "dir",
+            conflicts_with = "payloads",
+            required_unless_present = "payloads",
+            help = "Directory to search for payload files"
+        )]
+        dir: Option<PathBuf>,
+
+        #[arg(short = 'p', long = "payload",
+            value_parser = parse_payload_path,
+            help = "Payload of a modality given as INDEX=PATH. Repeat for multiple modalities."
+        )]
+        payloads: Vec<(usize, PathBuf)>,
+
+        #[arg(
+            long = "allow-legacy",
+            help = "Accept payloads whose modality_said was computed without their first 512 bytes by earlier versions"
+        )]
+        allow_legacy: bool,
+    },
+    /// Work with the provenance log of an MMIO
+    Provenance {
+        #[command(subcommand)]
+        command: ProvenanceCommand,
+    },
+    /// Manage the local store of OCA bundles, kept by SAID
+    Bundles {
+        #[arg(
+            long = "store",
+            default_value = DEFAULT_BUNDLE_STORE,
+            help = "Directory of the bundle store"
+        )]
+        store: PathBuf,
+
+        #[command(subcommand)]
+        command: BundlesCommand,
+    },
+    /// Pack an MMIO, its payloads and referenced bundles into one archive
+    Pack {
+        #[arg(long = "mmio")]
+        mmio: PathBuf,
+
+        #[arg(
+            long = "dir",
+            conflicts_with = "payloads",
+            required_unless_present = "payloads",
+            help = "Directory to search for payload files"
+        )]
+        dir: Option<PathBuf>,
+
+        #[arg(short = 'p', long = "payload",
+            value_parser = parse_payload_path,
+            help = "Payload of a modality given as INDEX=PATH. Repeat for multiple modalities."
+        )]
+        payloads: Vec<(usize, PathBuf)>,
+
+        #[arg(
+            long = "store",
+            default_value = DEFAULT_BUNDLE_STORE,
+            help = "Bundle store to take referenced bundles from"
+        )]
+        store: PathBuf,
+
+        #[arg(short, long)]
+        output: PathBuf,
+    },
+    /// Verify an MMIO archive and extract its content
+    Unpack {
+        archive: PathBuf,
+
+        #[arg(short, long, help = "Directory to extract to")]
+        output: PathBuf,
+    },
+    /// Link attributes of the tabular modality's bundle to another bundle
+    /// as described by a transformation OCAfile
+    Link {
+        #[arg(long = "mmio")]
+        mmio: PathBuf,
+
+        #[arg(
+            long = "ocafile",
+            help = "OCAfile with LINK ATTRIBUTE lines and a target=refs:<SAID> meta comment"
+        )]
+        ocafile: PathBuf,
+
+        #[arg(
+            long = "modality",
+            help = "Index of the modality to link, the tabular one if not given"
+        )]
+        modality: Option<usize>,
+
+        #[arg(short, long)]
+        output: PathBuf,
+    },
+    /// Convert an MMIO to another encoding, keeping its SAIDs
+    Convert {
+        input: PathBuf,
+
+        #[arg(long = "to", value_parser = parse_format, help = format_help())]
+        to: Format,
+
+        #[arg(long = "from", value_parser = parse_format,
+            help = "Encoding of the input, detected if not given"
+        )]
+        from: Option<Format>,
+
+        #[arg(short, long)]
+        output: PathBuf,
+    },
+    /// Manage the local content-addressed store of payloads
+    Store {
+        #[arg(
+            long = "store",
+            default_value = ".m2io/blobs",
+            help = "Directory of the payload store"
+        )]
+        store: PathBuf,
+
+        #[command(subcommand)]
+        command: StoreCommand,
     },
 }
 
-fn modality_type_from_mime(mime: &str) -> Option<ModalityType> {
-    if mime.starts_with("image/") {
-        Some(ModalityType::Image)
-    } else if mime.starts_with("text/") || mime == "application/json" {
-        Some(ModalityType::Text)
-    } else if mime.starts_with("audio/") {
-        Some(ModalityType::Audio)
-    } else if mime.starts_with("video/") {
-        Some(ModalityType::Video)
-    } else if mime == "application/octet-stream" || mime.starts_with("application/") {
-        Some(ModalityType::Binary)
-    } else {
-        // default to text
-        Some(ModalityType::Text)
-    }
+#[derive(Subcommand)]
+enum StoreCommand {
+    /// Add files to the store and print their SAIDs
+    Put {
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+
+        #[arg(long = "hash", value_parser = parse_hash, default_value = "blake3-256",
+            help = hash_help()
+        )]
+        hash: HashFunctionCode,
+    },
+    /// Write a stored payload, checking it against its SAID
+    Get {
+        said: SelfAddressingIdentifier,
+
+        #[arg(short, long, help = "Write to a file instead of standard output")]
+        output: Option<PathBuf>,
+    },
+    /// Check every stored payload against its SAID
+    Fsck,
+    /// Remove payloads no given MMIO refers to
+    Gc {
+        #[arg(long = "keep", required = true, num_args = 1..,
+            help = "MMIO files whose payloads are kept"
+        )]
+        keep: Vec<PathBuf>,
+
+        #[arg(
+            long = "dry-run",
+            help = "Only list the payloads that would be removed"
+        )]
+        dry_run: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum BundlesCommand {
+    /// Verify bundle JSON files and add them to the store
+    Add {
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+
+        #[arg(
+            long = "alias",
+            requires = "files",
+            help = "Also name the bundle by an alias such as Standard1@1.0. Only with a single file."
+        )]
+        alias: Option<String>,
+    },
+    /// List the SAIDs of stored bundles, with their aliases
+    List,
+    /// Print a stored bundle
+    Get {
+        said: SelfAddressingIdentifier,
+
+        #[arg(short, long, help = "Write to a file instead of standard output")]
+        output: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+enum ProvenanceCommand {
+    /// Export the provenance log as W3C PROV
+    Export {
+        #[arg(long = "mmio")]
+        mmio: PathBuf,
+
+        #[arg(long = "log", help = "Provenance log saved next to the MMIO")]
+        log: PathBuf,
+
+        #[arg(long = "format", value_parser = parse_prov_format, default_value = "prov-json",
+            help = "Output format: prov-json or ttl"
+        )]
+        format: ProvFormat,
+
+        #[arg(short, long, help = "Write to a file instead of standard output")]
+        output: Option<PathBuf>,
+    },
+}
+
+fn hash_help() -> String {
+    format!("Hash algorithm for SAIDs: {}", HASH_NAMES.join(", "))
+}
+
+fn parse_hash(s: &str) -> Result<HashFunctionCode, String> {
+    parse_hash_code(s).map_err(|e| e.to_string())
+}
+
+fn format_help() -> String {
+    format!("Encoding: {}", FORMAT_NAMES.join(", "))
+}
+
+fn parse_format(s: &str) -> Result<Format, String> {
+    s.parse().map_err(|e: m2io_tmp::MmioError| e.to_string())
+}
+
+fn parse_prov_format(s: &str) -> Result<ProvFormat, String> {
+    s.parse().map_err(|e: m2io_tmp::MmioError| e.to_string())
 }
 
-fn parse_modality(s: &str) -> Result<Modality, String> {
+fn parse_payload_path(s: &str) -> Result<(usize, PathBuf), String> {
+    let (index, path) = s
+        .split_once('=')
+        .ok_or_else(|| format!("Invalid payload format: {}", s))?;
+    let index = index
+        .parse()
+        .map_err(|e| format!("Invalid modality index '{}': {}", index, e))?;
+    Ok((index, PathBuf::from(path)))
+}
+
+#[derive(Clone)]
+enum SemanticSpec {
+    Reference(SelfAddressingIdentifier),
+    Ocafile(PathBuf),
+}
+
+#[derive(Clone)]
+struct ModalitySpec {
+    file: String,
+    semantic: SemanticSpec,
+    modality_type: Option<ModalityType>,
+    media_type: Option<String>,
+    hash: Option<HashFunctionCode>,
+}
+
+fn parse_modality(s: &str) -> Result<ModalitySpec, String> {
     let mut file = None;
-    let mut bundle_said = None;
+    let mut semantic = None;
     let mut modality_type = None;
     let mut media_type = None;
+    let mut hash = None;
 
     for part in s.split(',') {
         let mut kv = part.splitn(2, '=');
         match (kv.next(), kv.next()) {
             (Some("file"), Some(v)) => file = Some(v.to_string()),
             (Some("bundle_said"), Some(v)) => {
-                let said: SelfAddressingIdentifier = v.parse().unwrap();
-                bundle_said = Some(said);
+                let said: SelfAddressingIdentifier = v
+                    .parse()
+                    .map_err(|e| format!("Invalid bundle SAID '{}': {}", v, e))?;
+                if semantic.replace(SemanticSpec::Reference(said)).is_some() {
+                    return Err("Give either bundle_said or ocafile, not both.".to_string());
+                }
+            }
+            (Some("ocafile"), Some(v)) => {
+                if semantic.replace(SemanticSpec::Ocafile(v.into())).is_some() {
+                    return Err("Give either bundle_said or ocafile, not both.".to_string());
+                }
             }
             (Some("modality_type"), Some(v)) => {
-                let mt: ModalityType = v.parse().unwrap();
+                let mt: ModalityType = v.parse().map_err(|e| format!("{}", e))?;
                 modality_type = Some(mt);
             }
             (Some("media_type"), Some(v)) => media_type = Some(v.to_string()),
+            (Some("hash"), Some(v)) => hash = Some(parse_hash(v)?),
             _ => return Err(format!("Invalid modality format: {}", part)),
         }
     }
 
-    match (file, bundle_said) {
-        (Some(f), Some(b)) => {
-            let mut buf = [0; 512];
-            let mut file_reader = File::open(&f).map_err(|e| format!("Cannot open file: {}", e))?;
-            let n = file_reader
-                .read(&mut buf)
-                .map_err(|e| format!("Read error: {}", e))?;
-
-            if media_type.is_none() {
-                // Infer MIME type from file content
-                let mime = infer::get(&buf[..n])
-                    .map(|kind| kind.mime_type())
-                    .unwrap_or("application/octet-stream");
-                media_type = Some(mime.to_string());
-                if modality_type.is_none() {
-                    modality_type = modality_type_from_mime(mime);
-                }
-            }
-
-            let code = HashFunctionCode::Blake3_256;
-            let hash_algorithm = HashFunction::from(code.clone());
-
-            let said = hash_algorithm.derive_from_stream(file_reader).unwrap();
-
-            let mut modality = Modality {
-                digest: None,
-                modality_said: Some(said),
-                modality_type: modality_type.unwrap(),
-                media_type: media_type.unwrap(),
-                oca_bundle: Semantic::Reference(b),
-            };
-
-            modality.compute_digest();
-            Ok(modality)
-        }
+    match (file, semantic) {
+        (Some(file), Some(semantic)) => Ok(ModalitySpec {
+            file,
+            semantic,
+            modality_type,
+            media_type,
+            hash,
+        }),
         _ => Err("Both file and semantic must be provided.".to_string()),
     }
 }
 
+impl ModalitySpec {
+    /// Hash the file and build the modality, using `default_hash` unless the
+    /// spec names its own algorithm.
+    fn into_modality(self, default_hash: &HashFunctionCode) -> Result<Modality, String> {
+        let options = ModalityOptions {
+            media_type: self.media_type,
+            modality_type: self.modality_type,
+            hash: Some(self.hash.unwrap_or_else(|| default_hash.clone())),
+            locator: None,
+        };
+        let semantic = match self.semantic {
+            SemanticSpec::Reference(said) => Semantic::Reference(said),
+            SemanticSpec::Ocafile(path) => {
+                let source = fs::read_to_string(&path)
+                    .map_err(|e| format!("Cannot read '{}': {}", path.display(), e))?;
+                Semantic::from_ocafile(&source)
+                    .map_err(|e| format!("Cannot compile '{}': {}", path.display(), e))?
+            }
+        };
+        Modality::from_path(&self.file, semantic, &options)
+            .map_err(|e| format!("Cannot create modality from '{}': {}", self.file, e))
+    }
+}
+
 fn main() {
     let cli = Cli::parse();
 
     match cli.command {
-        Commands::Create { modalities, output } => {
+        Commands::Create {
+            modalities,
+            output,
+            hash,
+            format,
+        } => {
+            let modalities = modalities
+                .into_iter()
+                .map(|spec| spec.into_modality(&hash))
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap_or_else(|e| {
+                    eprintln!("{}", e);
+                    std::process::exit(1);
+                });
             let mut mmio = MMIO {
-                version: "0.1".to_string(),
+                version: MMIO_VERSION.to_string(),
                 digest: None,
                 modalities,
+                provenance: None,
+                log: Default::default(),
+                data: None,
             };
 
-            mmio.compute_digest();
+            mmio.compute_digest_with(hash)
+                .expect("Failed to compute MMIO digest");
 
-            let json = serde_json::to_string_pretty(&mmio).expect("Failed to serialize MMIO");
-            fs::write(&output, json).expect("Failed to write MMIO file");
+            let bytes = mmio.to_bytes(format).expect("Failed to serialize MMIO");
+            fs::write(&output, bytes).expect("Failed to write MMIO file");
             println!("MMIO object created at: {}", output.display());
         }
         Commands::Parse { mmio } => {
             let mut file = File::open(&mmio).expect("Failed to open MMIO file");
-            let mut contents = String::new();
-            file.read_to_string(&mut contents)
+            let mut contents = vec![];
+            file.read_to_end(&mut contents)
                 .expect("Failed to read MMIO file");
 
-            let mmio: MMIO = serde_json::from_str(&contents).expect("Failed to parse MMIO");
+            let mmio = MMIO::from_bytes(&contents, None).expect("Failed to parse MMIO");
             // Verify if the SAID are valid
-            for modality in &mmio.modalities {
-                if let Some(said) = &modality.digest {
-                    let mut m = modality.clone();
-                    m.compute_digest();
-                    assert_eq!(
-                        said,
-                        &m.digest.unwrap(),
-                        "SAID mismatch for modality: \n {:?}",
-                        modality
-                    );
-                } else {
-                    println!("No SAID found for modality: {:?}", modality);
+            let report = mmio.verify().expect("Failed to compute MMIO digests");
+            if !report.is_valid() {
+                for failure in report.failures() {
+                    eprintln!("{}", failure);
                 }
+                std::process::exit(1);
             }
-            // Verify if the SAID of MMIO is valid
-            let mut mmio_clone = mmio.clone();
-            mmio_clone.compute_digest();
-            assert_eq!(mmio.digest, mmio_clone.digest, "SAID mismatch for MMIO");
             println!("Parsed MMIO object is valid");
         }
-        Commands::Said { file } => {
-            let mut file_reader = File::open(&file).expect("Failed to open file");
-            let mut buf = [0; 512];
-            let n = file_reader.read(&mut buf).expect("Read error");
-            let mime = infer::get(&buf[..n])
-                .map(|kind| kind.mime_type())
-                .unwrap_or("application/octet-stream");
-
-            let code = HashFunctionCode::Blake3_256;
-            let hash_algorithm = HashFunction::from(code.clone());
-
-            let said = hash_algorithm.derive_from_stream(file_reader).unwrap();
+        Commands::Said { file, hash } => {
+            let file_reader = File::open(&file).expect("Failed to open file");
+            let (said, mime) = hash_payload(file_reader, &hash).expect("Read error");
             println!("MIME type: {}", mime);
             println!("SAID: {}", said);
         }
+        Commands::VerifyPayloads {
+            mmio: mmio_path,
+            dir,
+            payloads,
+            allow_legacy,
+        } => {
+            let contents = fs::read(&mmio_path).expect("Failed to read MMIO file");
+            let mmio = MMIO::from_bytes(&contents, None).expect("Failed to parse MMIO");
+
+            let mut report = match dir {
+                Some(dir) => mmio.verify_payloads_in_dir(&dir),
+                None => mmio.verify_payloads(&payloads.into_iter().collect()),
+            }
+            .expect("Failed to verify payloads");
+            // The manifest itself may live next to its payloads
+            if let Ok(manifest) = mmio_path.canonicalize() {
+                report
+                    .unexpected
+                    .retain(|p| p.canonicalize().map_or(true, |p| p != manifest));
+            }
+
+            for check in &report.payloads {
+                println!("{}", check);
+            }
+            for path in &report.unexpected {
+                println!("unexpected file: {}", path.display());
+            }
+            let valid = if allow_legacy {
+                report.is_valid_allowing_legacy()
+            } else {
+                report.is_valid()
+            };
+            if !valid {
+                std::process::exit(1);
+            }
+            println!("All payloads match their SAIDs");
+        }
+        Commands::Provenance {
+            command:
+                ProvenanceCommand::Export {
+                    mmio,
+                    log,
+                    format,
+                    output,
+                },
+        } => {
+            let contents = fs::read(&mmio).expect("Failed to read MMIO file");
+            let mut mmio = MMIO::from_bytes(&contents, None).expect("Failed to parse MMIO");
+            let log = fs::read_to_string(&log).expect("Failed to read provenance log");
+            mmio.log = ProvenanceLog::from_json(&log).unwrap_or_else(|e| {
+                eprintln!("Invalid provenance log: {}", e);
+                std::process::exit(1);
+            });
+            if let Err(e) = mmio.verify_provenance() {
+                eprintln!("Invalid provenance log: {}", e);
+                std::process::exit(1);
+            }
+
+            let exported = mmio.export_provenance(format).unwrap_or_else(|e| {
+                eprintln!("Failed to export provenance: {}", e);
+                std::process::exit(1);
+            });
+            match output {
+                Some(path) => fs::write(&path, exported).expect("Failed to write output file"),
+                None => println!("{}", exported),
+            }
+        }
+        Commands::Bundles { store, command } => {
+            let store = BundleStore::open(&store).unwrap_or_else(|e| {
+                eprintln!("Cannot open bundle store '{}': {}", store.display(), e);
+                std::process::exit(1);
+            });
+            match command {
+                BundlesCommand::Add { files, alias } => {
+                    if alias.is_some() && files.len() != 1 {
+                        eprintln!("--alias names a single bundle file");
+                        std::process::exit(1);
+                    }
+                    for file in files {
+                        let json = fs::read_to_string(&file).expect("Failed to read bundle file");
+                        let added = store.add_json(&json).and_then(|said| {
+                            if let Some(alias) = &alias {
+                                store.add_alias(alias, &said)?;
+                            }
+                            Ok(said)
+                        });
+                        match added {
+                            Ok(said) => println!("{}", said),
+                            Err(e) => {
+                                eprintln!("Cannot add '{}': {}", file.display(), e);
+                                std::process::exit(1);
+                            }
+                        }
+                    }
+                }
+                BundlesCommand::List => {
+                    let aliases = store.aliases().expect("Failed to read bundle aliases");
+                    for said in store.list().expect("Failed to list bundle store") {
+                        let names: Vec<&str> = aliases
+                            .iter()
+                            .filter(|(_, s)| **s == said)
+                            .map(|(alias, _)| alias.as_str())
+                            .collect();
+                        if names.is_empty() {
+                            println!("{}", said);
+                        } else {
+                            println!("{} {}", said, names.join(" "));
+                        }
+                    }
+                }
+                BundlesCommand::Get { said, output } => {
+                    let bundle = match store.get(&said) {
+                        Ok(Some(_)) => {
+                            fs::read_to_string(store.path(&said)).expect("Failed to read bundle")
+                        }
+                        Ok(None) => {
+                            eprintln!("Bundle {} is not in the store", said);
+                            std::process::exit(1);
+                        }
+                        Err(e) => {
+                            eprintln!("Invalid bundle {}: {}", said, e);
+                            std::process::exit(1);
+                        }
+                    };
+                    match output {
+                        Some(path) => {
+                            fs::write(&path, bundle).expect("Failed to write output file")
+                        }
+                        None => println!("{}", bundle),
+                    }
+                }
+            }
+        }
+        Commands::Pack {
+            mmio: mmio_path,
+            dir,
+            payloads,
+            store,
+            output,
+        } => {
+            let contents = fs::read(&mmio_path).expect("Failed to read MMIO file");
+            let mmio = MMIO::from_bytes(&contents, None).expect("Failed to parse MMIO");
+            let report = match dir {
+                Some(dir) => mmio.verify_payloads_in_dir(&dir),
+                None => mmio.verify_payloads(&payloads.into_iter().collect()),
+            }
+            .expect("Failed to verify payloads");
+            let mut archive = MmioArchive::new(mmio).unwrap_or_else(|e| {
+                eprintln!("Invalid MMIO: {}", e);
+                std::process::exit(1);
+            });
+            for check in &report.payloads {
+                let added = match (&check.status, &check.path) {
+                    (PayloadStatus::NoSaid, _) => continue,
+                    (PayloadStatus::Verified, Some(path)) => {
+                        archive.add_payload_file(check.index, path)
+                    }
+                    _ => {
+                        eprintln!("{}", check);
+                        std::process::exit(1);
+                    }
+                };
+                added.expect("Failed to read payload");
+            }
+            let store = BundleStore::open(&store).unwrap_or_else(|e| {
+                eprintln!("Cannot open bundle store '{}': {}", store.display(), e);
+                std::process::exit(1);
+            });
+            if let Err(e) = archive.add_referenced_bundles(&store) {
+                eprintln!("Cannot include referenced bundles: {}", e);
+                std::process::exit(1);
+            }
+            archive.save(&output).expect("Failed to write archive");
+            println!("MMIO archive created at: {}", output.display());
+        }
+        Commands::Unpack { archive, output } => {
+            let file = File::open(&archive).expect("Failed to open archive");
+            if let Err(e) = MmioArchive::extract(std::io::BufReader::new(file), &output) {
+                eprintln!("Invalid archive '{}': {}", archive.display(), e);
+                std::process::exit(1);
+            }
+            println!("MMIO archive extracted to: {}", output.display());
+        }
+        Commands::Link {
+            mmio: input,
+            ocafile,
+            modality,
+            output,
+        } => {
+            let contents = fs::read(&input).expect("Failed to read MMIO file");
+            let mut mmio = MMIO::from_bytes(&contents, None).unwrap_or_else(|e| {
+                eprintln!("Cannot parse '{}': {}", input.display(), e);
+                std::process::exit(1);
+            });
+            let source = fs::read_to_string(&ocafile).expect("Failed to read OCAfile");
+            let linked = Link::from_ocafile(&source)
+                .and_then(|link| match modality {
+                    Some(index) => mmio.link_modality(index, &link),
+                    None => mmio.link(&link),
+                })
+                .and_then(|_| mmio.compute_digests());
+            if let Err(e) = linked {
+                eprintln!("Cannot link '{}': {}", input.display(), e);
+                std::process::exit(1);
+            }
+            let format = Format::detect(&contents).unwrap_or_default();
+            let bytes = mmio.to_bytes(format).expect("Failed to serialize MMIO");
+            fs::write(&output, bytes).expect("Failed to write MMIO file");
+            println!("Linked MMIO written to: {}", output.display());
+        }
+        Commands::Convert {
+            input,
+            to,
+            from,
+            output,
+        } => {
+            let contents = fs::read(&input).expect("Failed to read MMIO file");
+            let mmio = MMIO::from_bytes(&contents, from).unwrap_or_else(|e| {
+                eprintln!("Cannot parse '{}': {}", input.display(), e);
+                std::process::exit(1);
+            });
+            let bytes = mmio.to_bytes(to).expect("Failed to serialize MMIO");
+            fs::write(&output, bytes).expect("Failed to write MMIO file");
+            println!("MMIO converted to {} at: {}", to, output.display());
+        }
+        Commands::Store { store, command } => {
+            let store = BlobStore::open(&store).unwrap_or_else(|e| {
+                eprintln!("Cannot open payload store '{}': {}", store.display(), e);
+                std::process::exit(1);
+            });
+            match command {
+                StoreCommand::Put { files, hash } => {
+                    for file in files {
+                        match store.put_file(&file, &hash) {
+                            Ok(said) => println!("{}", said),
+                            Err(e) => {
+                                eprintln!("Cannot add '{}': {}", file.display(), e);
+                                std::process::exit(1);
+                            }
+                        }
+                    }
+                }
+                StoreCommand::Get { said, output } => {
+                    let mut reader = match store.get(&said) {
+                        Ok(Some(reader)) => reader,
+                        Ok(None) => {
+                            eprintln!("Payload {} is not in the store", said);
+                            std::process::exit(1);
+                        }
+                        Err(e) => {
+                            eprintln!("Cannot read payload {}: {}", said, e);
+                            std::process::exit(1);
+                        }
+                    };
+                    let copied = match &output {
+                        Some(path) => File::create(path)
+                            .and_then(|mut file| std::io::copy(&mut reader, &mut file)),
+                        None => std::io::copy(&mut reader, &mut std::io::stdout().lock()),
+                    };
+                    if let Err(e) = copied {
+                        if let Some(path) = &output {
+                            let _ = fs::remove_file(path);
+                        }
+                        eprintln!("Cannot read payload {}: {}", said, e);
+                        std::process::exit(1);
+                    }
+                }
+                StoreCommand::Fsck => {
+                    let report = store.fsck().expect("Failed to check payload store");
+                    for (said, actual) in &report.corrupted {
+                        println!("corrupted: {}, computed {}", said, actual);
+                    }
+                    for path in &report.unexpected {
+                        println!("unexpected file: {}", path.display());
+                    }
+                    if !report.is_valid() {
+                        std::process::exit(1);
+                    }
+                    println!("All {} payloads match their SAIDs", report.checked);
+                }
+                StoreCommand::Gc { keep, dry_run } => {
+                    let mmios = keep
+                        .iter()
+                        .map(|path| {
+                            let contents = fs::read(path).expect("Failed to read MMIO file");
+                            MMIO::from_bytes(&contents, None).unwrap_or_else(|e| {
+                                eprintln!("Cannot parse '{}': {}", path.display(), e);
+                                std::process::exit(1);
+                            })
+                        })
+                        .collect::<Vec<_>>();
+                    let removed = if dry_run {
+                        store.unreferenced(&mmios)
+                    } else {
+                        store.gc(&mmios)
+                    }
+                    .expect("Failed to collect payloads");
+                    for said in &removed {
+                        println!("{}", said);
+                    }
+                }
+            }
+        }
     }
 }
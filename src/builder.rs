@@ -0,0 +1,266 @@
+use std::path::PathBuf;
+
+use oca_sdk_rs::OCABundleModel;
+use pyo3::prelude::*;
+use said::derivation::HashFunctionCode;
+use said::SelfAddressingIdentifier;
+
+use crate::{
+    parse_hash_code, parse_said, Locator, MmioError, Modality, ModalityOptions, ModalityType,
+    PyOCABundle, Semantic, DEFAULT_HASH, MMIO,
+};
+
+#[derive(Clone, Debug)]
+enum Payload {
+    File(PathBuf),
+    Bytes(Vec<u8>),
+}
+
+/// Builds a [`Modality`] from a payload and its semantics.
+///
+/// The payload is hashed into `modality_said` and the media and modality
+/// types are inferred from its content unless set explicitly.
+#[pyclass(name = "ModalityBuilder")]
+#[derive(Clone, Debug, Default)]
+pub struct ModalityBuilder {
+    payload: Option<Payload>,
+    semantic: Option<Semantic>,
+    options: ModalityOptions,
+}
+
+impl ModalityBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Use the content of the file at `path` as payload.
+    pub fn with_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.payload = Some(Payload::File(path.into()));
+        self
+    }
+
+    /// Use `bytes` as payload, of the given media type.
+    pub fn with_bytes(mut self, bytes: impl Into<Vec<u8>>, media_type: impl Into<String>) -> Self {
+        self.payload = Some(Payload::Bytes(bytes.into()));
+        self.options.media_type = Some(media_type.into());
+        self
+    }
+
+    /// Override the media type inferred from the payload.
+    pub fn with_media_type(mut self, media_type: impl Into<String>) -> Self {
+        self.options.media_type = Some(media_type.into());
+        self
+    }
+
+    /// Override the modality type derived from the media type.
+    pub fn with_modality_type(mut self, modality_type: ModalityType) -> Self {
+        self.options.modality_type = Some(modality_type);
+        self
+    }
+
+    pub fn with_semantic_reference(mut self, said: SelfAddressingIdentifier) -> Self {
+        self.semantic = Some(Semantic::Reference(said));
+        self
+    }
+
+    pub fn with_semantic_bundle(mut self, bundle: OCABundleModel) -> Self {
+        self.semantic = Some(Semantic::Bundle(bundle));
+        self
+    }
+
+    /// Record where the payload can be found.
+    pub fn with_locator(mut self, locator: Locator) -> Self {
+        self.options.locator = Some(locator);
+        self
+    }
+
+    /// Hash algorithm for `modality_said` and `digest`. When the modality is
+    /// added to an [`MmioBuilder`] this overrides the MMIO's algorithm.
+    pub fn with_hash(mut self, code: HashFunctionCode) -> Self {
+        self.options.hash = Some(code);
+        self
+    }
+
+    /// Hash the payload and return the modality with its digest computed.
+    pub fn build(self) -> Result<Modality, MmioError> {
+        self.build_with(&DEFAULT_HASH)
+    }
+
+    fn build_with(mut self, default_hash: &HashFunctionCode) -> Result<Modality, MmioError> {
+        self.options
+            .hash
+            .get_or_insert_with(|| default_hash.clone());
+        let semantic = self
+            .semantic
+            .ok_or_else(|| MmioError::InvalidModality("no semantic given".into()))?;
+        match self.payload {
+            Some(Payload::File(path)) => Modality::from_path(path, semantic, &self.options),
+            Some(Payload::Bytes(bytes)) => {
+                Modality::from_reader(bytes.as_slice(), semantic, &self.options)
+            }
+            None => Err(MmioError::InvalidModality("no payload given".into())),
+        }
+    }
+}
+
+/// Builds an [`MMIO`] of the current [`MMIO_VERSION`](crate::MMIO_VERSION) from its modalities.
+#[pyclass(name = "MmioBuilder")]
+#[derive(Clone, Debug, Default)]
+pub struct MmioBuilder {
+    modalities: Vec<ModalityBuilder>,
+    hash: Option<HashFunctionCode>,
+}
+
+impl MmioBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_modality(mut self, modality: ModalityBuilder) -> Self {
+        self.modalities.push(modality);
+        self
+    }
+
+    /// Hash algorithm for the MMIO digest and for modalities that do not set
+    /// their own. Defaults to [`DEFAULT_HASH`].
+    pub fn with_hash(mut self, code: HashFunctionCode) -> Self {
+        self.hash = Some(code);
+        self
+    }
+
+    /// Build every modality and compute all digests.
+    pub fn build(self) -> Result<MMIO, MmioError> {
+        let code = self.hash.unwrap_or(DEFAULT_HASH);
+        let mut mmio = MMIO::new();
+        for modality in self.modalities {
+            mmio.add_modality(modality.build_with(&code)?)?;
+        }
+        mmio.compute_digest_with(code)?;
+        Ok(mmio)
+    }
+}
+
+impl MMIO {
+    pub fn builder() -> MmioBuilder {
+        MmioBuilder::new()
+    }
+}
+
+impl Modality {
+    pub fn builder() -> ModalityBuilder {
+        ModalityBuilder::new()
+    }
+}
+
+/// Apply a consuming builder method in place, so Python calls can be chained.
+fn chain<T: pyo3::PyClass<Frozen = pyo3::pyclass::boolean_struct::False> + Default>(
+    mut slf: PyRefMut<'_, T>,
+    f: impl FnOnce(T) -> T,
+) -> PyRefMut<'_, T> {
+    let this = std::mem::take(&mut *slf);
+    *slf = f(this);
+    slf
+}
+
+#[pymethods]
+impl ModalityBuilder {
+    #[new]
+    fn py_new() -> Self {
+        Self::new()
+    }
+
+    #[pyo3(name = "with_file")]
+    fn py_with_file(slf: PyRefMut<'_, Self>, path: PathBuf) -> PyRefMut<'_, Self> {
+        chain(slf, |b| b.with_file(path))
+    }
+
+    #[pyo3(name = "with_bytes")]
+    fn py_with_bytes(
+        slf: PyRefMut<'_, Self>,
+        bytes: Vec<u8>,
+        media_type: String,
+    ) -> PyRefMut<'_, Self> {
+        chain(slf, |b| b.with_bytes(bytes, media_type))
+    }
+
+    #[pyo3(name = "with_media_type")]
+    fn py_with_media_type(slf: PyRefMut<'_, Self>, media_type: String) -> PyRefMut<'_, Self> {
+        chain(slf, |b| b.with_media_type(media_type))
+    }
+
+    #[pyo3(name = "with_modality_type")]
+    fn py_with_modality_type<'py>(
+        slf: PyRefMut<'py, Self>,
+        modality_type: &str,
+    ) -> PyResult<PyRefMut<'py, Self>> {
+        let modality_type: ModalityType = modality_type.parse()?;
+        Ok(chain(slf, |b| b.with_modality_type(modality_type)))
+    }
+
+    #[pyo3(name = "with_semantic_reference")]
+    fn py_with_semantic_reference<'py>(
+        slf: PyRefMut<'py, Self>,
+        said: &str,
+    ) -> PyResult<PyRefMut<'py, Self>> {
+        let said = parse_said(said)?;
+        Ok(chain(slf, |b| b.with_semantic_reference(said)))
+    }
+
+    #[pyo3(name = "with_semantic_bundle")]
+    fn py_with_semantic_bundle<'py>(
+        slf: PyRefMut<'py, Self>,
+        bundle: &PyOCABundle,
+    ) -> PyRefMut<'py, Self> {
+        let bundle = bundle.into_inner();
+        chain(slf, |b| b.with_semantic_bundle(bundle))
+    }
+
+    #[pyo3(name = "with_locator")]
+    fn py_with_locator<'py>(
+        slf: PyRefMut<'py, Self>,
+        kind: &str,
+        value: &str,
+    ) -> PyResult<PyRefMut<'py, Self>> {
+        let locator = Locator::from_parts(kind, value)?;
+        Ok(chain(slf, |b| b.with_locator(locator)))
+    }
+
+    #[pyo3(name = "with_hash")]
+    fn py_with_hash<'py>(slf: PyRefMut<'py, Self>, hash: &str) -> PyResult<PyRefMut<'py, Self>> {
+        let code = parse_hash_code(hash)?;
+        Ok(chain(slf, |b| b.with_hash(code)))
+    }
+
+    #[pyo3(name = "build")]
+    fn py_build(&self) -> PyResult<Modality> {
+        Ok(self.clone().build()?)
+    }
+}
+
+#[pymethods]
+impl MmioBuilder {
+    #[new]
+    fn py_new() -> Self {
+        Self::new()
+    }
+
+    #[pyo3(name = "add_modality")]
+    fn py_add_modality<'py>(
+        slf: PyRefMut<'py, Self>,
+        modality: &ModalityBuilder,
+    ) -> PyRefMut<'py, Self> {
+        let modality = modality.clone();
+        chain(slf, |b| b.add_modality(modality))
+    }
+
+    #[pyo3(name = "with_hash")]
+    fn py_with_hash<'py>(slf: PyRefMut<'py, Self>, hash: &str) -> PyResult<PyRefMut<'py, Self>> {
+        let code = parse_hash_code(hash)?;
+        Ok(chain(slf, |b| b.with_hash(code)))
+    }
+
+    #[pyo3(name = "build")]
+    fn py_build(&self) -> PyResult<MMIO> {
+        Ok(self.clone().build()?)
+    }
+}
@@ -0,0 +1,270 @@
+//! Canonical serialization used to derive MMIO and modality SAIDs.
+//!
+//! Starting with MMIO version 0.2 a SAID is computed as follows:
+//!
+//! 1. Serialize the object to JSON and remove the `locator` of the
+//!    modality, or of each modality of an MMIO. Where a payload is found is
+//!    not part of a SAID, the payload itself is covered by `modality_said`.
+//! 2. Replace the value of its `digest` field with a string of `#`
+//!    characters as long as the encoded SAID (44 for 256-bit digests, 88 for
+//!    512-bit ones).
+//! 3. Canonicalize the result with the JSON Canonicalization Scheme of
+//!    [RFC 8785](https://www.rfc-editor.org/rfc/rfc8785): no insignificant
+//!    whitespace, object members sorted by the UTF-16 code units of their
+//!    names, strings escaped as ECMAScript `JSON.stringify` does and numbers
+//!    in ECMAScript `Number.prototype.toString` form.
+//! 4. Hash the UTF-8 bytes and encode the digest as a CESR
+//!    self-addressing identifier.
+//!
+//! Version 0.1 documents hash the output of `serde_json` in struct field
+//! order through `said::make_me_happy`. That form depends on the key order
+//! of nested maps and is kept only so existing documents still verify.
+//! Locators are left out of it the same way. Because it hashes the document
+//! as serialized, early 0.1 documents, which name the digest `id` and embed
+//! bundles in a layout the OCA types no longer read, only verify from their
+//! JSON text, see [`legacy_said`].
+
+use said::derivation::{HashFunction, HashFunctionCode};
+use said::sad::DerivationCode;
+use said::{make_me_happy, SelfAddressingIdentifier};
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::MmioError;
+
+/// MMIO versions whose SAIDs use the legacy, non-canonical serialization.
+const LEGACY_VERSIONS: &[&str] = &["0.1"];
+
+const DIGEST_FIELD: &str = "digest";
+
+/// Name of the digest field in version 0.1 documents written before it was
+/// renamed to `digest`.
+const LEGACY_DIGEST_FIELD: &str = "id";
+
+const LOCATOR_FIELD: &str = "locator";
+
+/// Remove the locators from a serialized modality or MMIO.
+fn strip_locators(json: &mut Value) {
+    let Some(object) = json.as_object_mut() else {
+        return;
+    };
+    object.shift_remove(LOCATOR_FIELD);
+    if let Some(Value::Array(modalities)) = object.get_mut("modalities") {
+        for modality in modalities {
+            if let Some(modality) = modality.as_object_mut() {
+                modality.shift_remove(LOCATOR_FIELD);
+            }
+        }
+    }
+}
+
+/// Serialize a JSON value in RFC 8785 canonical form.
+pub fn canonicalize(value: &Value) -> String {
+    let mut out = String::new();
+    write_value(value, &mut out);
+    out
+}
+
+fn write_value(value: &Value, out: &mut String) {
+    match value {
+        Value::Null => out.push_str("null"),
+        Value::Bool(b) => out.push_str(if *b { "true" } else { "false" }),
+        // RFC 8785 treats every number as an IEEE 754 double, so integers
+        // beyond 2^53 lose precision exactly as they would in ECMAScript
+        Value::Number(n) => out.push_str(&format_number(n.as_f64().expect("finite number"))),
+        // serde_json escapes exactly the characters JSON.stringify does
+        Value::String(s) => out.push_str(&serde_json::to_string(s).expect("string serializes")),
+        Value::Array(items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_value(item, out);
+            }
+            out.push(']');
+        }
+        Value::Object(map) => {
+            let mut entries: Vec<_> = map.iter().collect();
+            entries.sort_by(|(a, _), (b, _)| a.encode_utf16().cmp(b.encode_utf16()));
+            out.push('{');
+            for (i, (key, item)) in entries.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                out.push_str(&serde_json::to_string(key).expect("string serializes"));
+                out.push(':');
+                write_value(item, out);
+            }
+            out.push('}');
+        }
+    }
+}
+
+/// Format a finite double the way ECMAScript `Number.prototype.toString`
+/// does.
+fn format_number(f: f64) -> String {
+    if f == 0.0 {
+        return "0".to_string();
+    }
+    // `{:e}` yields the shortest round-trip digits, e.g. `-1.25e-7`
+    let sci = format!("{:e}", f.abs());
+    let (mantissa, exp) = sci.split_once('e').expect("exponent present");
+    let digits: String = mantissa.chars().filter(|c| *c != '.').collect();
+    let exp: i32 = exp.parse().expect("exponent is an integer");
+    let k = digits.len() as i32;
+    let n = exp + 1;
+
+    let body = if k <= n && n <= 21 {
+        format!("{}{}", digits, "0".repeat((n - k) as usize))
+    } else if 0 < n && n <= 21 {
+        format!("{}.{}", &digits[..n as usize], &digits[n as usize..])
+    } else if -6 < n && n <= 0 {
+        format!("0.{}{}", "0".repeat((-n) as usize), digits)
+    } else {
+        let sign = if n - 1 < 0 { '-' } else { '+' };
+        let fraction = if k > 1 {
+            format!(".{}", &digits[1..])
+        } else {
+            String::new()
+        };
+        format!("{}{}e{}{}", &digits[..1], fraction, sign, (n - 1).abs())
+    };
+    if f < 0.0 {
+        format!("-{}", body)
+    } else {
+        body
+    }
+}
+
+/// Canonical form of `value` with its `digest` field replaced by the SAID
+/// placeholder for `code`. This is the exact input hashed into the SAID.
+pub fn digest_input<T: Serialize>(value: &T, code: &HashFunctionCode) -> Result<String, MmioError> {
+    let mut json = serde_json::to_value(value)?;
+    strip_locators(&mut json);
+    let object = json
+        .as_object_mut()
+        .ok_or_else(|| MmioError::SaidDerivation("only objects can carry a digest".into()))?;
+    object.insert(
+        DIGEST_FIELD.to_string(),
+        Value::String("#".repeat(code.full_size())),
+    );
+    Ok(canonicalize(&json))
+}
+
+/// Derive the version 0.1 SAID of a modality or MMIO from its JSON text as
+/// written, key order included. The digest is read from `digest`, or from
+/// `id` in documents that predate the name.
+pub fn legacy_said(
+    json: &str,
+    code: &HashFunctionCode,
+) -> Result<SelfAddressingIdentifier, MmioError> {
+    derive_legacy_said(serde_json::from_str(json)?, code)
+}
+
+fn derive_legacy_said(
+    mut json: Value,
+    code: &HashFunctionCode,
+) -> Result<SelfAddressingIdentifier, MmioError> {
+    strip_locators(&mut json);
+    let field = if json.get(DIGEST_FIELD).is_none() && json.get(LEGACY_DIGEST_FIELD).is_some() {
+        LEGACY_DIGEST_FIELD
+    } else {
+        DIGEST_FIELD
+    };
+    let serialized = serde_json::to_string(&json)?;
+    tracing::debug!(%serialized, "deriving legacy SAID");
+    let computed = make_me_happy(&serialized, code.clone(), Some(field))?;
+    let json: Value = serde_json::from_str(&computed)?;
+    json.get(field)
+        .and_then(Value::as_str)
+        .ok_or_else(|| MmioError::SaidDerivation("digest field not filled".into()))?
+        .parse()
+        .map_err(|e: said::error::Error| MmioError::SaidDerivation(e.to_string()))
+}
+
+/// Derive the SAID of `value` using the serialization rules of the given
+/// MMIO version.
+pub(crate) fn derive_said<T: Serialize>(
+    value: &T,
+    code: &HashFunctionCode,
+    version: &str,
+) -> Result<SelfAddressingIdentifier, MmioError> {
+    if LEGACY_VERSIONS.contains(&version) {
+        return derive_legacy_said(serde_json::to_value(value)?, code);
+    }
+
+    let canonical = digest_input(value, code)?;
+    tracing::debug!(%canonical, "deriving SAID");
+    Ok(HashFunction::from(code.clone()).derive(canonical.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use super::*;
+    use crate::{parse_hash_code, Modality, MMIO, MMIO_VERSION};
+
+    #[test]
+    fn test_canonical_json_vectors() {
+        let vectors: Vec<Value> =
+            serde_json::from_str(include_str!("../tests/vectors/canonical_json.json")).unwrap();
+        for vector in vectors {
+            let input: Value = serde_json::from_str(vector["input"].as_str().unwrap()).unwrap();
+            assert_eq!(canonicalize(&input), vector["canonical"].as_str().unwrap());
+        }
+    }
+
+    #[test]
+    fn test_said_vectors() {
+        let vectors: Vec<Value> =
+            serde_json::from_str(include_str!("../tests/vectors/said.json")).unwrap();
+        for vector in vectors {
+            let code = parse_hash_code(vector["hash"].as_str().unwrap()).unwrap();
+            let object = vector["object"].clone();
+            let said = match vector["kind"].as_str().unwrap() {
+                "modality" => {
+                    let mut modality: Modality = serde_json::from_value(object).unwrap();
+                    assert_eq!(
+                        digest_input(&modality, &code).unwrap(),
+                        vector["digest_input"].as_str().unwrap()
+                    );
+                    modality.compute_digest_with(code, MMIO_VERSION).unwrap()
+                }
+                _ => {
+                    let mut mmio: MMIO = serde_json::from_value(object).unwrap();
+                    assert_eq!(
+                        digest_input(&mmio, &code).unwrap(),
+                        vector["digest_input"].as_str().unwrap()
+                    );
+                    mmio.compute_digest_with(code).unwrap()
+                }
+            };
+            assert_eq!(
+                said.to_string(),
+                vector["said"].as_str().unwrap(),
+                "{}",
+                vector["description"]
+            );
+        }
+    }
+
+    #[test]
+    fn test_format_number() {
+        for (f, expected) in [
+            (0.0, "0"),
+            (-0.0, "0"),
+            (1.0, "1"),
+            (-1.5, "-1.5"),
+            (1e21, "1e+21"),
+            (1e20, "100000000000000000000"),
+            (1e-7, "1e-7"),
+            (0.000001, "0.000001"),
+            (123.456, "123.456"),
+            (5e-324, "5e-324"),
+        ] {
+            assert_eq!(format_number(f), expected);
+        }
+    }
+}
@@ -0,0 +1,225 @@
+//! CESR framing of MMIOs, for streams shared with the KERI and ACDC stack.
+//!
+//! A framed MMIO is a map whose first field `v` is a version string in the
+//! form KERI messages and OCA bundles use, e.g. `MMIO02JSON0001a2_`:
+//!
+//! - `MMIO`, the protocol;
+//! - two hex digits, the major and minor MMIO version;
+//! - `JSON`, `CBOR` or `MGPK`, the encoding of the map;
+//! - six hex digits, the size in bytes of the whole map;
+//! - `_`, the terminator.
+//!
+//! The size frames the message, so MMIOs can be concatenated into one
+//! stream and read back one by one. The version string is not part of any
+//! SAID, which stay as the [`canonical`](crate::canonical) module defines.
+
+use std::io::Read;
+
+use said::version::format::SerializationFormats;
+use said::version::SerializationInfo;
+use serde_json::{Map, Value};
+
+use crate::{Format, MmioError, MMIO};
+
+/// Protocol code of MMIO version strings.
+pub const CESR_PROTOCOL: &str = "MMIO";
+
+const VERSION_FIELD: &str = "v";
+
+const VERSION_STRING_LEN: usize = 17;
+
+/// Bytes at the start of a message the version string must begin within.
+const VERSION_SEARCH_LEN: usize = 12;
+
+const MAX_SIZE: usize = 0xff_ffff;
+
+impl From<Format> for SerializationFormats {
+    fn from(format: Format) -> Self {
+        match format {
+            Format::Json => SerializationFormats::JSON,
+            Format::Cbor => SerializationFormats::CBOR,
+            Format::MsgPack => SerializationFormats::MGPK,
+        }
+    }
+}
+
+impl From<SerializationFormats> for Format {
+    fn from(format: SerializationFormats) -> Self {
+        match format {
+            SerializationFormats::JSON => Format::Json,
+            SerializationFormats::CBOR => Format::Cbor,
+            SerializationFormats::MGPK => Format::MsgPack,
+        }
+    }
+}
+
+fn invalid(message: impl Into<String>) -> MmioError {
+    MmioError::Encoding(format!("CESR: {}", message.into()))
+}
+
+/// Major and minor version digits of an MMIO version such as `0.2`.
+fn version_digits(version: &str) -> Result<(u8, u8), MmioError> {
+    let digit = |s: &str| u8::from_str_radix(s, 16).ok().filter(|d| *d < 16);
+    version
+        .split_once('.')
+        .and_then(|(major, minor)| Some((digit(major)?, digit(minor)?)))
+        .ok_or_else(|| invalid(format!("version '{}' has no version string form", version)))
+}
+
+/// Parse a version string. Unlike `SerializationInfo::from_str`, sizes
+/// above 64 KiB are read in full.
+fn parse_version_string(s: &[u8]) -> Option<SerializationInfo> {
+    let s = std::str::from_utf8(s.get(..VERSION_STRING_LEN)?).ok()?;
+    if !s.starts_with(CESR_PROTOCOL) || !s.ends_with('_') {
+        return None;
+    }
+    let hex = |range: std::ops::Range<usize>| usize::from_str_radix(&s[range], 16).ok();
+    Some(SerializationInfo::new(
+        CESR_PROTOCOL.to_string(),
+        hex(4..5)? as u8,
+        hex(5..6)? as u8,
+        s[6..10].parse().ok()?,
+        hex(10..16)?,
+    ))
+}
+
+/// Find the version string at the start of a message.
+fn find_version_string(bytes: &[u8]) -> Option<SerializationInfo> {
+    (0..VERSION_SEARCH_LEN).find_map(|i| parse_version_string(bytes.get(i..)?))
+}
+
+impl MMIO {
+    /// Encode the MMIO as a CESR message in the given encoding.
+    pub fn to_cesr(&self, format: Format) -> Result<Vec<u8>, MmioError> {
+        let (major, minor) = version_digits(&self.version)?;
+        let Value::Object(fields) = serde_json::to_value(self)? else {
+            unreachable!("an MMIO serializes to a map");
+        };
+        let mut info =
+            SerializationInfo::new(CESR_PROTOCOL.to_string(), major, minor, format.into(), 0);
+        let encode = |info: &SerializationInfo| {
+            let mut message = Map::new();
+            message.insert(VERSION_FIELD.to_string(), Value::String(info.to_str()));
+            message.extend(fields.clone());
+            match format {
+                // Messages are framed by size, so there is no need for newlines
+                Format::Json => Ok(serde_json::to_vec(&message)?),
+                _ => format.encode(&message),
+            }
+        };
+        // The version string has a fixed width, so the size of the message
+        // does not change once it is filled in
+        info.size = encode(&info)?.len();
+        if info.size > MAX_SIZE {
+            return Err(invalid(format!(
+                "a message of {} bytes does not fit the version string",
+                info.size
+            )));
+        }
+        encode(&info)
+    }
+
+    /// Decode an MMIO from exactly one CESR message.
+    pub fn from_cesr(bytes: &[u8]) -> Result<Self, MmioError> {
+        let (mmio, size) = decode_message(bytes)?;
+        if size != bytes.len() {
+            return Err(invalid(format!(
+                "{} bytes follow the message",
+                bytes.len() - size
+            )));
+        }
+        Ok(mmio)
+    }
+}
+
+/// Decode the message at the start of `bytes`, returning it with its size.
+fn decode_message(bytes: &[u8]) -> Result<(MMIO, usize), MmioError> {
+    let info = find_version_string(bytes).ok_or_else(|| invalid("no MMIO version string"))?;
+    let frame = bytes.get(..info.size).ok_or_else(|| {
+        invalid(format!(
+            "the message is {} bytes but only {} are left",
+            info.size,
+            bytes.len()
+        ))
+    })?;
+    let format = Format::from(info.kind);
+    let Value::Object(mut fields) = format.decode::<Value>(frame)? else {
+        return Err(invalid("the message is not a map"));
+    };
+    let first = fields.keys().next().is_some_and(|key| key == VERSION_FIELD);
+    match fields.shift_remove(VERSION_FIELD) {
+        Some(Value::String(v)) if first && v == info.to_str() => {}
+        _ => return Err(invalid("the version string is not the first field `v`")),
+    }
+    let mmio = MMIO::from_bytes(&serde_json::to_vec(&fields)?, Some(Format::Json))?;
+    if version_digits(&mmio.version)? != (info.major_version, info.minor_version) {
+        return Err(invalid(format!(
+            "version string {} does not match MMIO version {}",
+            info.to_str(),
+            mmio.version
+        )));
+    }
+    Ok((mmio, info.size))
+}
+
+/// Decode every MMIO of a stream of concatenated CESR messages.
+pub fn parse_cesr_stream(bytes: &[u8]) -> Result<Vec<MMIO>, MmioError> {
+    CesrReader::new(bytes).collect()
+}
+
+/// Reads the MMIOs of a stream of concatenated CESR messages one at a time.
+pub struct CesrReader<R> {
+    reader: R,
+    buffer: Vec<u8>,
+    done: bool,
+}
+
+impl<R: Read> CesrReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            buffer: vec![],
+            done: false,
+        }
+    }
+
+    /// Read until the buffer holds `len` bytes or the stream ends.
+    fn fill(&mut self, len: usize) -> Result<(), MmioError> {
+        if self.buffer.len() < len {
+            let missing = (len - self.buffer.len()) as u64;
+            (&mut self.reader)
+                .take(missing)
+                .read_to_end(&mut self.buffer)?;
+        }
+        Ok(())
+    }
+
+    fn next_message(&mut self) -> Result<Option<MMIO>, MmioError> {
+        self.fill(VERSION_SEARCH_LEN + VERSION_STRING_LEN)?;
+        if self.buffer.is_empty() {
+            return Ok(None);
+        }
+        let info =
+            find_version_string(&self.buffer).ok_or_else(|| invalid("no MMIO version string"))?;
+        self.fill(info.size)?;
+        let (mmio, size) = decode_message(&self.buffer)?;
+        self.buffer.drain(..size);
+        Ok(Some(mmio))
+    }
+}
+
+impl<R: Read> Iterator for CesrReader<R> {
+    type Item = Result<MMIO, MmioError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let next = self.next_message();
+        // Framing is lost after an error, so nothing more can be read
+        if !matches!(next, Ok(Some(_))) {
+            self.done = true;
+        }
+        next.transpose()
+    }
+}
@@ -0,0 +1,298 @@
+use std::collections::HashMap;
+use std::io::{Cursor, Read};
+use std::path::{Path, PathBuf};
+
+use oca_sdk_rs::{AttributeType, NestedAttrType, OCABundleModel};
+use polars::prelude::{Column, DataFrame, DataType, IpcReader, IpcWriter, SerReader, SerWriter};
+use pyo3::prelude::*;
+use pyo3_polars::PyDataFrame;
+use said::derivation::{HashFunction, HashFunctionCode};
+
+use crate::semantics::{is_boolean, is_date};
+use crate::{
+    hash_code_of, stored_bundle, Event, FeedEvent, MmioError, ModalityType, Semantic, DEFAULT_HASH,
+    MMIO,
+};
+
+/// Media type of the Arrow IPC file a tabular modality's `modality_said` is
+/// computed over.
+pub const ARROW_MEDIA_TYPE: &str = "application/vnd.apache.arrow.file";
+
+/// Records ingested into the tabular modality of an MMIO.
+#[pyclass(name = "Data")]
+#[derive(Debug)]
+pub struct TabularData {
+    /// Index of the modality the records belong to.
+    pub modality: usize,
+    pub records: DataFrame,
+    /// The MMIO the records were read from in Python, for `Data.to`.
+    pub(crate) origin: Option<Py<MMIO>>,
+}
+
+impl Clone for TabularData {
+    fn clone(&self) -> Self {
+        Self {
+            modality: self.modality,
+            records: self.records.clone(),
+            origin: self
+                .origin
+                .as_ref()
+                .map(|origin| Python::attach(|py| origin.clone_ref(py))),
+        }
+    }
+}
+
+impl TabularData {
+    pub fn new(modality: usize, records: DataFrame) -> Self {
+        Self {
+            modality,
+            records,
+            origin: None,
+        }
+    }
+
+    /// The records as a single-chunk Arrow IPC file, the payload of the
+    /// modality. Chunking is normalised so that the same records always
+    /// serialize to the same bytes.
+    pub fn to_ipc(&self) -> Result<Vec<u8>, MmioError> {
+        let mut records = self.records.clone();
+        records.as_single_chunk();
+        let mut ipc = vec![];
+        IpcWriter::new(&mut ipc).finish(&mut records)?;
+        Ok(ipc)
+    }
+
+    /// Write the payload to `path`, e.g. for `m2io verify-payloads`.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), MmioError> {
+        std::fs::write(path, self.to_ipc()?)?;
+        Ok(())
+    }
+}
+
+impl MMIO {
+    /// Validate `data` against the capture base of the tabular modality and
+    /// append it to the records of that modality.
+    ///
+    /// The modality's `modality_said` is recomputed over the Arrow IPC file
+    /// of all records ingested so far and the digests are cleared. A
+    /// [`FeedEvent`] referencing the batch is recorded in the log.
+    ///
+    /// If the modality already commits to records, as in a document read
+    /// back from JSON, they must be loaded with [`MMIO::load_records`]
+    /// first, so that none are dropped from `modality_said`.
+    pub fn ingest(&mut self, data: &DataFrame) -> Result<(), MmioError> {
+        let index = match &self.data {
+            Some(current) => current.modality,
+            None => self.tabular_modality()?,
+        };
+        let bundle = self.embedded_bundle(index)?;
+        if self.data.is_none() && self.modalities[index].modality_said.is_some() {
+            return Err(MmioError::InvalidModality(format!(
+                "modality {} already commits to records, load them with load_records before ingesting more",
+                index
+            )));
+        }
+        validate_records(bundle, data)?;
+
+        let records = match &self.data {
+            Some(current) => {
+                let columns = current.records.get_column_names_owned();
+                if data.width() != columns.len() {
+                    return Err(mismatched_columns(data, &current.records));
+                }
+                let batch = data
+                    .select(columns)
+                    .map_err(|_| mismatched_columns(data, &current.records))?;
+                current.records.vstack(&batch)?
+            }
+            None => data.clone(),
+        };
+        let tabular = TabularData::new(index, records);
+
+        let code: HashFunctionCode = self.modalities[index]
+            .modality_said
+            .as_ref()
+            .map_or(DEFAULT_HASH, hash_code_of);
+        let said = HashFunction::from(code).derive(&tabular.to_ipc()?);
+        self.log
+            .add_event(Event::Feed(FeedEvent::new(data, false)?))?;
+
+        let modality = &mut self.modalities[index];
+        modality.modality_said = Some(said);
+        modality.media_type = ARROW_MEDIA_TYPE.to_string();
+        modality.digest = None;
+        self.digest = None;
+        self.data = Some(tabular);
+        Ok(())
+    }
+
+    /// Load the records the tabular modality commits to from the Arrow IPC
+    /// file read from `reader`, after checking it against `modality_said`.
+    pub fn load_records<R: Read>(&mut self, mut reader: R) -> Result<(), MmioError> {
+        let index = self.tabular_modality()?;
+        let expected = self.modalities[index]
+            .modality_said
+            .clone()
+            .ok_or_else(|| {
+                MmioError::InvalidModality(format!("modality {} commits to no records", index))
+            })?;
+        let mut ipc = vec![];
+        reader.read_to_end(&mut ipc)?;
+        let actual = HashFunction::from(hash_code_of(&expected)).derive(&ipc);
+        if actual != expected {
+            return Err(MmioError::SaidMismatch { expected, actual });
+        }
+        let records = IpcReader::new(Cursor::new(ipc)).finish()?;
+        self.data = Some(TabularData::new(index, records));
+        Ok(())
+    }
+
+    /// Index of the first tabular modality.
+    pub(crate) fn tabular_modality(&self) -> Result<usize, MmioError> {
+        self.modalities
+            .iter()
+            .position(|m| matches!(m.modality_type, ModalityType::Tabular))
+            .ok_or_else(|| MmioError::InvalidModality("no tabular modality".into()))
+    }
+
+    /// The ingested records, holding a reference to the Python MMIO for
+    /// `Data.to`.
+    pub(crate) fn attached_data(mmio: &Bound<'_, MMIO>) -> Option<TabularData> {
+        let mut data = mmio.borrow().data.clone()?;
+        data.origin = Some(mmio.clone().unbind());
+        Some(data)
+    }
+
+    /// The bundle embedded in the modality at `index`.
+    pub(crate) fn embedded_bundle(&self, index: usize) -> Result<&OCABundleModel, MmioError> {
+        let modality = self.modalities.get(index).ok_or_else(|| {
+            MmioError::InvalidModality(format!("modality {} no longer exists", index))
+        })?;
+        match &modality.oca_bundle {
+            Semantic::Bundle(bundle) => Ok(bundle),
+            Semantic::Reference(said) => Err(MmioError::InvalidModality(format!(
+                "modality {} only references bundle {}, embed it to validate records",
+                index, said
+            ))),
+        }
+    }
+}
+
+fn mismatched_columns(data: &DataFrame, records: &DataFrame) -> MmioError {
+    MmioError::InvalidRecords(format!(
+        "columns {:?} differ from the ingested columns {:?}",
+        data.get_column_names_str(),
+        records.get_column_names_str()
+    ))
+}
+
+/// Check the columns of `data` against the capture base of `bundle`. Every
+/// column must be an attribute of a matching type. Booleans and dates may
+/// also be given as text. Attributes without a column are allowed, as in the
+/// OCA data validator.
+pub fn validate_records(bundle: &OCABundleModel, data: &DataFrame) -> Result<(), MmioError> {
+    let attributes = &bundle.capture_base.attributes;
+    let mut errors = vec![];
+    for column in data.get_columns() {
+        let name = column.name();
+        match attributes.get(name.as_str()) {
+            None | Some(NestedAttrType::Null) => {
+                errors.push(format!("column '{}' is not in the capture base", name))
+            }
+            Some(attr_type) if !accepts_column(attr_type, column)? => errors.push(format!(
+                "column '{}' of type {} does not match attribute type {:?}",
+                name,
+                column.dtype(),
+                attr_type
+            )),
+            Some(_) => {}
+        }
+    }
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(MmioError::InvalidRecords(errors.join(", ")))
+    }
+}
+
+fn accepts_column(attr_type: &NestedAttrType, column: &Column) -> Result<bool, MmioError> {
+    if !column.dtype().is_string() {
+        return Ok(accepts(attr_type, column.dtype()));
+    }
+    let text_check: fn(&str) -> bool = match attr_type {
+        NestedAttrType::Value(AttributeType::Boolean) => is_boolean,
+        NestedAttrType::Value(AttributeType::DateTime) => is_date,
+        _ => return Ok(accepts(attr_type, column.dtype())),
+    };
+    let values = column.as_materialized_series().str()?;
+    Ok(values.into_iter().flatten().all(text_check))
+}
+
+fn accepts(attr_type: &NestedAttrType, dtype: &DataType) -> bool {
+    if matches!(dtype, DataType::Null) {
+        return true;
+    }
+    match attr_type {
+        NestedAttrType::Value(AttributeType::Text) => {
+            dtype.is_string() || dtype.is_categorical() || dtype.is_enum()
+        }
+        NestedAttrType::Value(AttributeType::Numeric) => dtype.is_numeric(),
+        NestedAttrType::Value(AttributeType::Boolean) => dtype.is_bool(),
+        NestedAttrType::Value(AttributeType::DateTime) => dtype.is_temporal(),
+        NestedAttrType::Value(AttributeType::Binary) => matches!(dtype, DataType::Binary),
+        NestedAttrType::Array(inner) => match dtype {
+            DataType::List(item) => accepts(inner, item),
+            _ => false,
+        },
+        // The structure of a referenced bundle is not known here
+        NestedAttrType::Reference(_) => true,
+        NestedAttrType::Null => false,
+    }
+}
+
+#[pymethods]
+impl TabularData {
+    #[getter]
+    fn get_records(&self) -> PyDataFrame {
+        PyDataFrame(self.records.clone())
+    }
+
+    #[getter]
+    fn get_modality(&self) -> usize {
+        self.modality
+    }
+
+    /// Write the records as an Arrow IPC file, the payload of the modality.
+    #[pyo3(name = "save")]
+    fn py_save(&self, path: std::path::PathBuf) -> PyResult<()> {
+        Ok(self.save(path)?)
+    }
+
+    /// Transform the records along the links of their bundle to the one
+    /// given as `{"standard": "<bundle SAID or name@version>"}`, read from
+    /// the bundle store at `store`.
+    #[pyo3(signature = (target, store=None))]
+    fn to(
+        &self,
+        py: Python<'_>,
+        target: HashMap<String, String>,
+        store: Option<PathBuf>,
+    ) -> PyResult<TabularData> {
+        let said = target.get("standard").ok_or_else(|| {
+            MmioError::InvalidSemantics("expected a target as {\"standard\": SAID}".into())
+        })?;
+        let origin = self.origin.as_ref().ok_or_else(|| {
+            MmioError::InvalidModality("the records are not attached to an MMIO".into())
+        })?;
+        let transformed = origin
+            .bind(py)
+            .borrow()
+            .transform(&stored_bundle(said, store)?)?;
+        Ok(MMIO::attached_data(&Bound::new(py, transformed)?)
+            .expect("transformed MMIOs hold records"))
+    }
+
+    fn __len__(&self) -> usize {
+        self.records.height()
+    }
+}
@@ -0,0 +1,109 @@
+use pyo3::exceptions::PyOSError;
+use pyo3::PyErr;
+use said::SelfAddressingIdentifier;
+use thiserror::Error;
+
+/// Errors returned by MMIO library entry points.
+#[derive(Error, Debug)]
+pub enum MmioError {
+    #[error("Serialization error: {0}")]
+    Serialization(#[from] serde_json::Error),
+
+    #[error("Encoding error: {0}")]
+    Encoding(String),
+
+    #[error("Failed to derive SAID: {0}")]
+    SaidDerivation(String),
+
+    #[error("SAID mismatch: expected {expected}, computed {actual}")]
+    SaidMismatch {
+        expected: SelfAddressingIdentifier,
+        actual: SelfAddressingIdentifier,
+    },
+
+    #[error("Unsupported MMIO version: '{0}'")]
+    UnsupportedVersion(String),
+
+    #[error("Invalid modality: {0}")]
+    InvalidModality(String),
+
+    #[error("Invalid SAID: {0}")]
+    InvalidSaid(String),
+
+    #[error("Unsupported hash algorithm: {0}")]
+    UnsupportedHash(String),
+
+    #[error("Invalid provenance log: {0}")]
+    InvalidProvenance(String),
+
+    #[error("Invalid semantics: {0}")]
+    InvalidSemantics(String),
+
+    #[error("Invalid OCAfile at line {line}: {message}")]
+    InvalidOcafile { line: usize, message: String },
+
+    #[error("Records do not match the semantics: {0}")]
+    InvalidRecords(String),
+
+    #[error("Invalid archive: {0}")]
+    InvalidArchive(String),
+
+    #[error("Data error: {0}")]
+    Data(#[from] polars::error::PolarsError),
+
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+impl From<said::version::error::Error> for MmioError {
+    fn from(e: said::version::error::Error) -> Self {
+        MmioError::SaidDerivation(e.to_string())
+    }
+}
+
+/// Python exception types raised by the `m2io_tmp` module. All of them derive
+/// from `MmioError`, so Python callers can catch either the base class or a
+/// specific failure.
+pub mod exceptions {
+    use pyo3::create_exception;
+    use pyo3::exceptions::PyException;
+
+    create_exception!(m2io_tmp, MmioError, PyException);
+    create_exception!(m2io_tmp, SerializationError, MmioError);
+    create_exception!(m2io_tmp, SaidDerivationError, MmioError);
+    create_exception!(m2io_tmp, SaidMismatchError, MmioError);
+    create_exception!(m2io_tmp, UnsupportedVersionError, MmioError);
+    create_exception!(m2io_tmp, InvalidModalityError, MmioError);
+    create_exception!(m2io_tmp, InvalidSaidError, MmioError);
+    create_exception!(m2io_tmp, UnsupportedHashError, MmioError);
+    create_exception!(m2io_tmp, InvalidProvenanceError, MmioError);
+    create_exception!(m2io_tmp, InvalidSemanticsError, MmioError);
+    create_exception!(m2io_tmp, InvalidOcafileError, InvalidSemanticsError);
+    create_exception!(m2io_tmp, InvalidRecordsError, MmioError);
+    create_exception!(m2io_tmp, InvalidArchiveError, MmioError);
+    create_exception!(m2io_tmp, DataError, MmioError);
+}
+
+impl From<MmioError> for PyErr {
+    fn from(e: MmioError) -> Self {
+        let msg = e.to_string();
+        match e {
+            MmioError::Serialization(_) | MmioError::Encoding(_) => {
+                exceptions::SerializationError::new_err(msg)
+            }
+            MmioError::SaidDerivation(_) => exceptions::SaidDerivationError::new_err(msg),
+            MmioError::SaidMismatch { .. } => exceptions::SaidMismatchError::new_err(msg),
+            MmioError::UnsupportedVersion(_) => exceptions::UnsupportedVersionError::new_err(msg),
+            MmioError::InvalidModality(_) => exceptions::InvalidModalityError::new_err(msg),
+            MmioError::InvalidSaid(_) => exceptions::InvalidSaidError::new_err(msg),
+            MmioError::UnsupportedHash(_) => exceptions::UnsupportedHashError::new_err(msg),
+            MmioError::InvalidProvenance(_) => exceptions::InvalidProvenanceError::new_err(msg),
+            MmioError::InvalidSemantics(_) => exceptions::InvalidSemanticsError::new_err(msg),
+            MmioError::InvalidOcafile { .. } => exceptions::InvalidOcafileError::new_err(msg),
+            MmioError::InvalidRecords(_) => exceptions::InvalidRecordsError::new_err(msg),
+            MmioError::InvalidArchive(_) => exceptions::InvalidArchiveError::new_err(msg),
+            MmioError::Data(_) => exceptions::DataError::new_err(msg),
+            MmioError::Io(_) => PyOSError::new_err(msg),
+        }
+    }
+}
@@ -1,13 +1,44 @@
-use crate::{MMRecord, MMIOBundle};
+use polars::prelude::{DataFrame, IpcReader, IpcWriter, SerReader, SerWriter};
 use pyo3::prelude::*;
-use pyo3::types::IntoPyDict;
 use pyo3_polars::PyDataFrame;
+use said::derivation::{HashFunction, HashFunctionCode};
+use said::SelfAddressingIdentifier;
 use serde::{Deserialize, Serialize};
-use std::{any::type_name, time::SystemTime};
+use std::io::Cursor;
+use std::sync::RwLock;
+use std::time::{SystemTime, UNIX_EPOCH};
 
-#[derive(Debug, Serialize, Deserialize)]
+use crate::{canonicalize, digest_input, hash_code_of, MmioError, Semantic, DEFAULT_HASH};
+
+/// Events recorded while an MMIO is being worked on, oldest first.
+///
+/// Each entry carries its own SAID and the SAID of the entry before it, so
+/// editing, removing or reordering entries breaks the chain. Dropping entries
+/// from the end can only be detected against a head recorded elsewhere, see
+/// [`ProvenanceLog::verify_head`].
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct ProvenanceLog {
-    pub events: Vec<Event>,
+    pub events: Vec<LogEntry>,
+}
+
+/// An event chained to the entry before it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogEntry {
+    /// SAID over `previous` and the event, computed like MMIO digests.
+    pub digest: Option<SelfAddressingIdentifier>,
+    /// SAID of the preceding entry, `None` for the first one.
+    pub previous: Option<SelfAddressingIdentifier>,
+    pub event: Event,
+}
+
+impl LogEntry {
+    fn compute_digest(
+        &self,
+        code: &HashFunctionCode,
+    ) -> Result<SelfAddressingIdentifier, MmioError> {
+        let input = digest_input(self, code)?;
+        Ok(HashFunction::from(code.clone()).derive(input.as_bytes()))
+    }
 }
 
 impl ProvenanceLog {
@@ -15,26 +46,89 @@ impl ProvenanceLog {
         Self { events: vec![] }
     }
 
-    pub fn add_event(&mut self, e: Event) {
-        self.events.push(e);
+    /// Append an event, chaining it to the current head. Returns the SAID of
+    /// the new entry.
+    pub fn add_event(&mut self, event: Event) -> Result<SelfAddressingIdentifier, MmioError> {
+        let mut entry = LogEntry {
+            digest: None,
+            previous: self.head().cloned(),
+            event,
+        };
+        let digest = entry.compute_digest(&DEFAULT_HASH)?;
+        entry.digest = Some(digest.clone());
+        self.events.push(entry);
+        Ok(digest)
+    }
+
+    /// Parse a saved log, verifying its chain.
+    pub fn from_json(json: &str) -> Result<Self, MmioError> {
+        let log: ProvenanceLog = serde_json::from_str(json)?;
+        log.verify()?;
+        Ok(log)
     }
-}
 
-// type EventBox = Box<dyn Event + Send + Sync + 'static>;
-//
-// pub trait Event {
-//     fn get_event(&self) -> String;
-//     fn get_event_type(&self) -> String {
-//         let type_name = type_name::<Self>().to_string();
-//         type_name.split("::").last().unwrap().to_string()
-//     }
-// }
+    pub fn to_json(&self) -> Result<String, MmioError> {
+        Ok(serde_json::to_string(self)?)
+    }
 
+    /// SAID of the latest entry.
+    pub fn head(&self) -> Option<&SelfAddressingIdentifier> {
+        self.events.last().and_then(|e| e.digest.as_ref())
+    }
 
-#[derive(Debug, Serialize, Deserialize)]
-#[serde(tag = "type")] // Optional: for tagged serialization
+    /// Check that every entry matches its SAID and follows the one before it.
+    pub fn verify(&self) -> Result<(), MmioError> {
+        let mut previous = None;
+        for (i, entry) in self.events.iter().enumerate() {
+            if entry.previous.as_ref() != previous {
+                return Err(MmioError::InvalidProvenance(match i {
+                    0 => "the first event refers to a previous one".to_string(),
+                    _ => format!("event {} does not follow event {}", i, i - 1),
+                }));
+            }
+            let expected = entry.digest.as_ref().ok_or_else(|| {
+                MmioError::InvalidProvenance(format!("event {} has no digest", i))
+            })?;
+            let actual = entry.compute_digest(&hash_code_of(expected))?;
+            if actual != *expected {
+                return Err(MmioError::InvalidProvenance(format!(
+                    "event {} was modified: expected {}, computed {}",
+                    i, expected, actual
+                )));
+            }
+            previous = Some(expected);
+        }
+        Ok(())
+    }
+
+    /// Verify the chain and check that it contains `head`, so no entry up
+    /// to the recorded head was dropped.
+    pub fn verify_head(&self, head: &SelfAddressingIdentifier) -> Result<(), MmioError> {
+        self.verify()?;
+        if !self.events.iter().any(|e| e.digest.as_ref() == Some(head)) {
+            return Err(MmioError::InvalidProvenance(format!(
+                "recorded head {} is not in the log",
+                head
+            )));
+        }
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type")]
 pub enum Event {
+    ModalityAdded(ModalityAddedEvent),
     LoadBundle(LoadBundleEvent),
+    DigestComputed(DigestComputedEvent),
     Feed(FeedEvent),
     Transform(TransformEvent),
 }
@@ -42,7 +136,9 @@ pub enum Event {
 impl Event {
     pub fn get_event_type(&self) -> &'static str {
         match self {
+            Event::ModalityAdded(_) => "ModalityAddedEvent",
             Event::LoadBundle(_) => "LoadBundleEvent",
+            Event::DigestComputed(_) => "DigestComputedEvent",
             Event::Feed(_) => "FeedEvent",
             Event::Transform(_) => "TransformEvent",
         }
@@ -50,114 +146,341 @@ impl Event {
 
     pub fn get_event(&self) -> String {
         match self {
+            Event::ModalityAdded(e) => e.get_event(),
             Event::LoadBundle(e) => e.get_event(),
+            Event::DigestComputed(e) => e.get_event(),
             Event::Feed(e) => e.get_event(),
             Event::Transform(e) => e.get_event(),
         }
     }
+
+    pub fn time(&self) -> SystemTime {
+        match self {
+            Event::ModalityAdded(e) => e.time,
+            Event::LoadBundle(e) => e.time,
+            Event::DigestComputed(e) => e.time,
+            Event::Feed(e) => e.time,
+            Event::Transform(e) => e.time,
+        }
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct Sys {
-    user: String,
-    version: String,
+/// Environment an event was recorded in.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Sys {
+    /// `None` when user identity is redacted.
+    pub user: Option<String>,
+    /// `None` when user identity is redacted or the name is unknown.
+    #[serde(default)]
+    pub hostname: Option<String>,
+    /// Operating system and architecture, e.g. `linux-x86_64`.
+    #[serde(default)]
+    pub os: String,
+    /// Version of this crate, including the git SHA of development builds.
+    #[serde(default)]
+    pub m2io_version: String,
+    #[serde(default)]
+    pub git_sha: Option<String>,
+    /// Python version when running inside the extension module.
+    #[serde(default, alias = "version")]
+    pub python_version: Option<String>,
 }
 
 impl Sys {
-    fn new() -> Self {
-        let mut user = String::new();
-        let mut version = String::new();
-
-        let r = Python::with_gil(|py| -> PyResult<()> {
-            let sys = py.import_bound("sys")?;
-            version = sys.getattr("version")?.extract()?;
-
-            let locals =
-                [("os", py.import_bound("os")?)].into_py_dict_bound(py);
-            let code =
-                "os.getenv('USER') or os.getenv('USERNAME') or 'Unknown'"
-                    .to_string();
-            user = py.eval_bound(&code, None, Some(&locals))?.extract()?;
-
-            Ok(())
-        });
-
-        if let Err(e) = r {
-            eprintln!("Error: {:?}", e);
+    /// Capture the environment with the installed [`EnvironmentCapture`].
+    pub(crate) fn new() -> Self {
+        CAPTURE.read().unwrap_or_else(|e| e.into_inner()).capture()
+    }
+}
+
+/// Source of the [`Sys`] recorded with every event. Install one with
+/// [`set_environment_capture`].
+pub trait EnvironmentCapture: Send + Sync {
+    fn capture(&self) -> Sys;
+}
+
+/// Reads the environment of the current process. This is the default.
+#[derive(Debug, Clone, Default)]
+pub struct SystemEnvironment {
+    /// Leave out the user and host names.
+    pub redact_user: bool,
+}
+
+impl EnvironmentCapture for SystemEnvironment {
+    fn capture(&self) -> Sys {
+        let (user, hostname) = if self.redact_user {
+            (None, None)
+        } else {
+            (
+                std::env::var("USER")
+                    .or_else(|_| std::env::var("USERNAME"))
+                    .ok(),
+                hostname(),
+            )
+        };
+        Sys {
+            user,
+            hostname,
+            os: format!("{}-{}", std::env::consts::OS, std::env::consts::ARCH),
+            m2io_version: env!("M2IO_VERSION").to_string(),
+            git_sha: option_env!("M2IO_GIT_SHA").map(str::to_string),
+            python_version: Python::try_attach(|py| py.version().to_string()),
         }
-        Sys { user, version }
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+fn hostname() -> Option<String> {
+    std::env::var("HOSTNAME")
+        .or_else(|_| std::env::var("COMPUTERNAME"))
+        .ok()
+        .or_else(|| std::fs::read_to_string("/proc/sys/kernel/hostname").ok())
+        .or_else(|| std::fs::read_to_string("/etc/hostname").ok())
+        .map(|h| h.trim().to_string())
+        .filter(|h| !h.is_empty())
+}
+
+lazy_static::lazy_static! {
+    static ref CAPTURE: RwLock<Box<dyn EnvironmentCapture>> =
+        RwLock::new(Box::new(SystemEnvironment::default()));
+}
+
+/// Replace the environment capture used for events recorded from now on,
+/// e.g. to redact user identity or to record a fixed environment in tests.
+pub fn set_environment_capture(capture: impl EnvironmentCapture + 'static) {
+    *CAPTURE.write().unwrap_or_else(|e| e.into_inner()) = Box::new(capture);
+}
+
+/// A modality was appended to `MMIO.modalities`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModalityAddedEvent {
+    pub time: SystemTime,
+    pub sys: Sys,
+    pub index: usize,
+    pub modality_said: Option<SelfAddressingIdentifier>,
+    pub media_type: String,
+}
+
+impl ModalityAddedEvent {
+    pub fn new(
+        index: usize,
+        modality_said: Option<SelfAddressingIdentifier>,
+        media_type: String,
+    ) -> Self {
+        Self {
+            time: SystemTime::now(),
+            sys: Sys::new(),
+            index,
+            modality_said,
+            media_type,
+        }
+    }
+    pub fn get_event(&self) -> String {
+        format!(
+            "ModalityAddedEvent: {:?}, modality {} ({})",
+            self.sys, self.index, self.media_type
+        )
+    }
+}
+
+/// An OCA bundle was attached to a modality as its semantic.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LoadBundleEvent {
-    time: SystemTime,
-    sys: Sys,
-    bundle: MMIOBundle,
+    pub time: SystemTime,
+    pub sys: Sys,
+    pub index: usize,
+    /// SAID of the bundle, either referenced or embedded.
+    pub bundle: Option<SelfAddressingIdentifier>,
 }
 
 impl LoadBundleEvent {
-    pub fn new(bundle: MMIOBundle) -> Self {
+    pub fn new(index: usize, semantic: &Semantic) -> Self {
+        let bundle = match semantic {
+            Semantic::Reference(said) => Some(said.clone()),
+            Semantic::Bundle(bundle) => bundle.digest.clone(),
+        };
         Self {
             time: SystemTime::now(),
             sys: Sys::new(),
+            index,
             bundle,
         }
     }
     pub fn get_event(&self) -> String {
-        format!("LoadBundleEvent: {:?}, {:#?}", self.sys, self.bundle)
+        format!(
+            "LoadBundleEvent: {:?}, modality {}, bundle {}",
+            self.sys,
+            self.index,
+            self.bundle
+                .as_ref()
+                .map_or_else(|| "-".to_string(), |s| s.to_string())
+        )
     }
 }
 
-#[derive(Debug, Serialize)]
-pub struct FeedEvent {
-    time: SystemTime,
-    sys: Sys,
-    #[serde(skip)]
-    data: MMRecord,
-}
-
-impl<'de> Deserialize<'de> for FeedEvent {
-    fn deserialize<D>(_deserializer: D) -> Result<Self, D::Error>
-    where
-        D: serde::Deserializer<'de>,
-    {
-        let event = FeedEvent {
+/// A digest was recomputed, of a modality or of the MMIO itself.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DigestComputedEvent {
+    pub time: SystemTime,
+    pub sys: Sys,
+    /// Modality index, or `None` for the MMIO digest.
+    pub index: Option<usize>,
+    pub digest: SelfAddressingIdentifier,
+}
+
+impl DigestComputedEvent {
+    pub fn new(index: Option<usize>, digest: SelfAddressingIdentifier) -> Self {
+        Self {
             time: SystemTime::now(),
             sys: Sys::new(),
-            data: dummy_record(),
+            index,
+            digest,
+        }
+    }
+    pub fn get_event(&self) -> String {
+        let subject = match self.index {
+            Some(i) => format!("modality {}", i),
+            None => "MMIO".to_string(),
+        };
+        format!(
+            "DigestComputedEvent: {:?}, {} {}",
+            self.sys, subject, self.digest
+        )
+    }
+}
+
+/// Content reference of a batch of records, enough to recognise the data
+/// without storing it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DataReference {
+    /// SAID of the records in Arrow IPC file format.
+    pub said: SelfAddressingIdentifier,
+    pub rows: usize,
+    /// SAID of the column names and data types, in order.
+    pub schema: SelfAddressingIdentifier,
+}
+
+impl DataReference {
+    /// Compute the reference of `data`, returning it with the Arrow IPC
+    /// serialization it was derived from. The records are written as a
+    /// single chunk, so the SAID does not depend on how they were batched.
+    pub fn of(data: &DataFrame) -> Result<(Self, Vec<u8>), MmioError> {
+        let mut records = data.clone();
+        records.as_single_chunk();
+        let mut ipc = vec![];
+        IpcWriter::new(&mut ipc).finish(&mut records)?;
+        let reference = DataReference {
+            said: HashFunction::from(DEFAULT_HASH).derive(&ipc),
+            rows: data.height(),
+            schema: schema_fingerprint(data)?,
         };
-        Ok(event)
+        Ok((reference, ipc))
     }
 }
 
-fn dummy_record() -> MMRecord {
-    // Create a dummy MMRecord here
-        Python::with_gil(|py| {
-        let pd = py.import_bound("pandas").unwrap();
-        let df = pd.call_method0("DataFrame").unwrap();
-        df.extract::<PyDataFrame>().unwrap()
-    })
+/// SAID over the canonical JSON list of `[name, data type]` pairs.
+pub fn schema_fingerprint(data: &DataFrame) -> Result<SelfAddressingIdentifier, MmioError> {
+    let columns: Vec<_> = data
+        .schema()
+        .iter()
+        .map(|(name, dtype)| serde_json::json!([name.as_str(), dtype.to_string()]))
+        .collect();
+    let canonical = canonicalize(&serde_json::Value::Array(columns));
+    Ok(HashFunction::from(DEFAULT_HASH).derive(canonical.as_bytes()))
+}
+
+/// Records were fed into the MMIO.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FeedEvent {
+    pub time: SystemTime,
+    pub sys: Sys,
+    pub data: DataReference,
+    /// The records in Arrow IPC file format, if kept in the log. Serialized
+    /// as base64.
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "base64_bytes"
+    )]
+    pub payload: Option<Vec<u8>>,
 }
 
 impl FeedEvent {
-    pub fn new(data: PyDataFrame) -> Self {
-        Self {
+    /// Record `data` by reference, keeping a copy of the records in the log
+    /// if `inline` is set.
+    pub fn new(data: &DataFrame, inline: bool) -> Result<Self, MmioError> {
+        let (reference, ipc) = DataReference::of(data)?;
+        Ok(Self {
             time: SystemTime::now(),
             sys: Sys::new(),
-            data,
+            data: reference,
+            payload: inline.then_some(ipc),
+        })
+    }
+
+    /// The inline records, checked against the data reference. `None` if
+    /// the event only holds a reference.
+    pub fn records(&self) -> Result<Option<DataFrame>, MmioError> {
+        let Some(ipc) = &self.payload else {
+            return Ok(None);
+        };
+        let code = hash_code_of(&self.data.said);
+        let actual = HashFunction::from(code).derive(ipc);
+        if actual != self.data.said {
+            return Err(MmioError::SaidMismatch {
+                expected: self.data.said.clone(),
+                actual,
+            });
         }
+        Ok(Some(IpcReader::new(Cursor::new(ipc)).finish()?))
     }
+
     pub fn get_event(&self) -> String {
-        format!("FeedEvent: {:?}, {:#?}", self.sys, self.data.0)
+        format!(
+            "FeedEvent: {:?}, {} rows ({})",
+            self.sys, self.data.rows, self.data.said
+        )
     }
 }
 
+mod base64_bytes {
+    use base64::{engine::general_purpose::STANDARD, Engine};
+    use serde::{Deserialize, Deserializer, Serializer};
 
-#[derive(Debug, Serialize, Deserialize)]
+    pub fn serialize<S: Serializer>(bytes: &Option<Vec<u8>>, s: S) -> Result<S::Ok, S::Error> {
+        match bytes {
+            Some(bytes) => s.serialize_some(&STANDARD.encode(bytes)),
+            None => s.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Option<Vec<u8>>, D::Error> {
+        Option::<String>::deserialize(d)?
+            .map(|s| STANDARD.decode(s).map_err(serde::de::Error::custom))
+            .transpose()
+    }
+}
+
+/// Records were transformed to the semantics of another bundle.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TransformEvent {
-    time: SystemTime,
-    sys: Sys,
+    pub time: SystemTime,
+    pub sys: Sys,
+    /// Bundle whose link overlay was applied.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<SelfAddressingIdentifier>,
+    /// Bundle the records were transformed to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<SelfAddressingIdentifier>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub input: Option<DataReference>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output: Option<DataReference>,
+}
+
+impl Default for TransformEvent {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl TransformEvent {
@@ -165,10 +488,92 @@ impl TransformEvent {
         Self {
             time: SystemTime::now(),
             sys: Sys::new(),
+            source: None,
+            target: None,
+            input: None,
+            output: None,
         }
     }
+
+    /// Record the transformation of `input` into `output` along the links
+    /// from bundle `source` to bundle `target`.
+    pub fn linked(
+        source: Option<SelfAddressingIdentifier>,
+        target: SelfAddressingIdentifier,
+        input: &DataFrame,
+        output: &DataFrame,
+    ) -> Result<Self, MmioError> {
+        Ok(Self {
+            source,
+            target: Some(target),
+            input: Some(DataReference::of(input)?.0),
+            output: Some(DataReference::of(output)?.0),
+            ..Self::new()
+        })
+    }
+
     pub fn get_event(&self) -> String {
-        format!("TransformEvent: {:?}", self.sys)
+        match &self.target {
+            Some(target) => format!("TransformEvent: {:?}, to bundle {}", self.sys, target),
+            None => format!("TransformEvent: {:?}", self.sys),
+        }
     }
 }
 
+/// Read-only view of a [`LogEntry`] for Python.
+#[pyclass(name = "Event")]
+#[derive(Clone)]
+pub struct PyEvent {
+    inner: LogEntry,
+}
+
+impl From<LogEntry> for PyEvent {
+    fn from(inner: LogEntry) -> Self {
+        Self { inner }
+    }
+}
+
+#[pymethods]
+impl PyEvent {
+    #[getter]
+    fn get_type(&self) -> &'static str {
+        self.inner.event.get_event_type()
+    }
+
+    #[getter]
+    fn get_digest(&self) -> Option<String> {
+        self.inner.digest.as_ref().map(|said| said.to_string())
+    }
+
+    #[getter]
+    fn get_previous(&self) -> Option<String> {
+        self.inner.previous.as_ref().map(|said| said.to_string())
+    }
+
+    /// Seconds since the Unix epoch.
+    #[getter]
+    fn get_time(&self) -> f64 {
+        self.inner
+            .event
+            .time()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0.0, |d| d.as_secs_f64())
+    }
+
+    fn to_json(&self) -> PyResult<String> {
+        Ok(serde_json::to_string(&self.inner).map_err(MmioError::from)?)
+    }
+
+    /// Records kept inline by a feed event, `None` for other events and for
+    /// feeds recorded by reference only.
+    fn records(&self) -> PyResult<Option<PyDataFrame>> {
+        match &self.inner.event {
+            Event::Feed(feed) => Ok(feed.records()?.map(PyDataFrame)),
+            _ => Ok(None),
+        }
+    }
+
+    fn __str__(&self) -> String {
+        self.inner.event.get_event()
+    }
+}
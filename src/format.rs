@@ -0,0 +1,127 @@
+//! Encodings of MMIO documents.
+//!
+//! Besides JSON an MMIO can be encoded as CBOR or MessagePack, which keep
+//! manifests with embedded bundles compact. All encodings share the data
+//! model of the JSON form: structs are maps keyed by field name and SAIDs
+//! are strings, so a document converts between encodings without loss.
+//!
+//! SAIDs do not depend on the encoding. Whatever encoding a document is
+//! read from, its digests are derived over the serialization its version
+//! defines (see the [`canonical`](crate::canonical) module), so converting
+//! a document never changes its SAID.
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::{MmioError, MMIO, SUPPORTED_VERSIONS};
+
+/// Encoding of a serialized MMIO.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Format {
+    #[default]
+    Json,
+    Cbor,
+    MsgPack,
+}
+
+/// Names accepted by [`Format::from_str`](std::str::FromStr), as shown in
+/// CLI help.
+pub const FORMAT_NAMES: &[&str] = &["json", "cbor", "msgpack"];
+
+impl std::str::FromStr for Format {
+    type Err = MmioError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "json" => Ok(Format::Json),
+            "cbor" => Ok(Format::Cbor),
+            "msgpack" | "mgpk" => Ok(Format::MsgPack),
+            _ => Err(MmioError::Encoding(format!(
+                "unknown format '{}'. Valid formats are: {}",
+                s,
+                FORMAT_NAMES.join(", ")
+            ))),
+        }
+    }
+}
+
+impl std::fmt::Display for Format {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Format::Json => write!(f, "json"),
+            Format::Cbor => write!(f, "cbor"),
+            Format::MsgPack => write!(f, "msgpack"),
+        }
+    }
+}
+
+impl Format {
+    /// Guess the encoding of a serialized map, such as an MMIO, from its
+    /// first byte.
+    pub fn detect(bytes: &[u8]) -> Option<Self> {
+        match bytes.iter().find(|b| !b.is_ascii_whitespace())? {
+            b'{' => Some(Format::Json),
+            // CBOR maps are major type 5
+            0xa0..=0xbf => Some(Format::Cbor),
+            // MessagePack fixmap, map 16 and map 32
+            0x80..=0x8f | 0xde | 0xdf => Some(Format::MsgPack),
+            _ => None,
+        }
+    }
+
+    /// Encode `value`. JSON is pretty printed as by the CLI.
+    pub fn encode<T: Serialize>(&self, value: &T) -> Result<Vec<u8>, MmioError> {
+        match self {
+            Format::Json => Ok(serde_json::to_vec_pretty(value)?),
+            Format::Cbor => {
+                let mut bytes = vec![];
+                ciborium::into_writer(value, &mut bytes)
+                    .map_err(|e| MmioError::Encoding(format!("CBOR: {}", e)))?;
+                Ok(bytes)
+            }
+            Format::MsgPack => rmp_serde::to_vec_named(value)
+                .map_err(|e| MmioError::Encoding(format!("MessagePack: {}", e))),
+        }
+    }
+
+    pub fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, MmioError> {
+        match self {
+            Format::Json => Ok(serde_json::from_slice(bytes)?),
+            Format::Cbor => {
+                let mut rest = bytes;
+                let value = ciborium::from_reader(&mut rest)
+                    .map_err(|e| MmioError::Encoding(format!("CBOR: {}", e)))?;
+                if !rest.is_empty() {
+                    return Err(MmioError::Encoding(format!(
+                        "CBOR: {} bytes follow the value",
+                        rest.len()
+                    )));
+                }
+                Ok(value)
+            }
+            Format::MsgPack => rmp_serde::from_slice(bytes)
+                .map_err(|e| MmioError::Encoding(format!("MessagePack: {}", e))),
+        }
+    }
+}
+
+impl MMIO {
+    /// Serialize the MMIO in the given encoding.
+    pub fn to_bytes(&self, format: Format) -> Result<Vec<u8>, MmioError> {
+        format.encode(self)
+    }
+
+    /// Parse an MMIO in the given encoding, or the one detected with
+    /// [`Format::detect`] if `None`, rejecting versions this implementation
+    /// does not support.
+    pub fn from_bytes(bytes: &[u8], format: Option<Format>) -> Result<Self, MmioError> {
+        let format = format
+            .or_else(|| Format::detect(bytes))
+            .ok_or_else(|| MmioError::Encoding("cannot detect the encoding".into()))?;
+        let mmio: MMIO = format.decode(bytes)?;
+        if !SUPPORTED_VERSIONS.contains(&mmio.version.as_str()) {
+            return Err(MmioError::UnsupportedVersion(mmio.version));
+        }
+        Ok(mmio)
+    }
+}
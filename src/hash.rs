@@ -0,0 +1,132 @@
+use std::io::Read;
+
+use blake2::{digest::consts::U32, Blake2b, Blake2b512, Blake2s256, Digest};
+use said::derivation::{HashFunction, HashFunctionCode};
+use said::SelfAddressingIdentifier;
+use sha2::{Sha256, Sha512};
+use sha3::{Sha3_256, Sha3_512};
+
+use crate::MmioError;
+
+type Blake2b256 = Blake2b<U32>;
+
+/// Hash algorithm used when a document does not already carry a digest.
+pub const DEFAULT_HASH: HashFunctionCode = HashFunctionCode::Blake3_256;
+
+/// Names accepted by [`parse_hash_code`], as shown in CLI help.
+pub const HASH_NAMES: &[&str] = &[
+    "blake3-256",
+    "blake3-512",
+    "blake2b-256",
+    "blake2b-512",
+    "blake2s-256",
+    "sha2-256",
+    "sha2-512",
+    "sha3-256",
+    "sha3-512",
+];
+
+/// Parse a hash algorithm name such as `sha2-256` or `blake3`.
+pub fn parse_hash_code(s: &str) -> Result<HashFunctionCode, MmioError> {
+    match s.to_lowercase().replace('_', "-").as_str() {
+        "blake3" | "blake3-256" => Ok(HashFunctionCode::Blake3_256),
+        "blake3-512" => Ok(HashFunctionCode::Blake3_512),
+        "blake2b" | "blake2b-256" => Ok(HashFunctionCode::Blake2B256),
+        "blake2b-512" => Ok(HashFunctionCode::Blake2B512),
+        "blake2s" | "blake2s-256" => Ok(HashFunctionCode::Blake2S256),
+        "sha256" | "sha-256" | "sha2-256" => Ok(HashFunctionCode::SHA2_256),
+        "sha512" | "sha-512" | "sha2-512" => Ok(HashFunctionCode::SHA2_512),
+        "sha3" | "sha3-256" => Ok(HashFunctionCode::SHA3_256),
+        "sha3-512" => Ok(HashFunctionCode::SHA3_512),
+        _ => Err(MmioError::UnsupportedHash(format!(
+            "'{}'. Valid algorithms are: {}",
+            s,
+            HASH_NAMES.join(", ")
+        ))),
+    }
+}
+
+/// Hash algorithm a SAID was derived with, read from its CESR prefix.
+pub fn hash_code_of(said: &SelfAddressingIdentifier) -> HashFunctionCode {
+    (&said.derivation).into()
+}
+
+/// Incremental hasher for any supported algorithm, for payloads hashed as
+/// they are read.
+pub(crate) enum StreamHasher {
+    Blake3(Box<blake3::Hasher>, usize),
+    Blake2B256(Blake2b256),
+    Blake2B512(Blake2b512),
+    Blake2S256(Blake2s256),
+    Sha2_256(Sha256),
+    Sha2_512(Sha512),
+    Sha3_256(Sha3_256),
+    Sha3_512(Sha3_512),
+}
+
+impl StreamHasher {
+    pub(crate) fn new(code: &HashFunctionCode) -> Self {
+        match code {
+            HashFunctionCode::Blake3_256 => StreamHasher::Blake3(Box::default(), 32),
+            HashFunctionCode::Blake3_512 => StreamHasher::Blake3(Box::default(), 64),
+            HashFunctionCode::Blake2B256 => StreamHasher::Blake2B256(Blake2b256::new()),
+            HashFunctionCode::Blake2B512 => StreamHasher::Blake2B512(Blake2b512::new()),
+            HashFunctionCode::Blake2S256 => StreamHasher::Blake2S256(Blake2s256::new()),
+            HashFunctionCode::SHA2_256 => StreamHasher::Sha2_256(Sha256::new()),
+            HashFunctionCode::SHA2_512 => StreamHasher::Sha2_512(Sha512::new()),
+            HashFunctionCode::SHA3_256 => StreamHasher::Sha3_256(Sha3_256::new()),
+            HashFunctionCode::SHA3_512 => StreamHasher::Sha3_512(Sha3_512::new()),
+        }
+    }
+
+    pub(crate) fn update(&mut self, data: &[u8]) {
+        match self {
+            StreamHasher::Blake3(hasher, _) => {
+                hasher.update(data);
+            }
+            StreamHasher::Blake2B256(hasher) => hasher.update(data),
+            StreamHasher::Blake2B512(hasher) => hasher.update(data),
+            StreamHasher::Blake2S256(hasher) => hasher.update(data),
+            StreamHasher::Sha2_256(hasher) => hasher.update(data),
+            StreamHasher::Sha2_512(hasher) => hasher.update(data),
+            StreamHasher::Sha3_256(hasher) => hasher.update(data),
+            StreamHasher::Sha3_512(hasher) => hasher.update(data),
+        }
+    }
+
+    pub(crate) fn finalize(self, code: &HashFunctionCode) -> SelfAddressingIdentifier {
+        let digest = match self {
+            StreamHasher::Blake3(hasher, len) => {
+                let mut out = vec![0u8; len];
+                hasher.finalize_xof().fill(&mut out);
+                out
+            }
+            StreamHasher::Blake2B256(hasher) => hasher.finalize().to_vec(),
+            StreamHasher::Blake2B512(hasher) => hasher.finalize().to_vec(),
+            StreamHasher::Blake2S256(hasher) => hasher.finalize().to_vec(),
+            StreamHasher::Sha2_256(hasher) => hasher.finalize().to_vec(),
+            StreamHasher::Sha2_512(hasher) => hasher.finalize().to_vec(),
+            StreamHasher::Sha3_256(hasher) => hasher.finalize().to_vec(),
+            StreamHasher::Sha3_512(hasher) => hasher.finalize().to_vec(),
+        };
+        SelfAddressingIdentifier::new(HashFunction::from(code.clone()), digest)
+    }
+}
+
+/// Derive a SAID from a stream with any supported algorithm. Unlike
+/// `HashFunction::derive_from_stream`, this is not limited to Blake3.
+pub fn derive_from_reader<R: Read>(
+    code: &HashFunctionCode,
+    mut reader: R,
+) -> std::io::Result<SelfAddressingIdentifier> {
+    let mut hasher = StreamHasher::new(code);
+    let mut buffer = [0u8; 65536];
+    loop {
+        let n = reader.read(&mut buffer)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buffer[..n]);
+    }
+    Ok(hasher.finalize(code))
+}
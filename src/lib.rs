@@ -1,10 +1,64 @@
 #![allow(dead_code)]
 
-use oca_sdk_rs::OCABundleModel;
-use pyo3::{exceptions::PyValueError, prelude::*};
+mod archive;
+mod builder;
+mod canonical;
+mod cesr;
+mod data;
+mod error;
+mod events;
+mod format;
+mod hash;
+mod locator;
+mod payload;
+mod prov;
+mod resolve;
+mod semantics;
+mod store;
+mod transform;
+mod verify;
+
+pub use archive::MmioArchive;
+pub use builder::{MmioBuilder, ModalityBuilder};
+pub use canonical::{canonicalize, digest_input, legacy_said};
+pub use cesr::{parse_cesr_stream, CesrReader, CESR_PROTOCOL};
+pub use data::{validate_records, TabularData, ARROW_MEDIA_TYPE};
+pub use error::{exceptions, MmioError};
+pub use events::{
+    schema_fingerprint, set_environment_capture, DataReference, DigestComputedEvent,
+    EnvironmentCapture, Event, FeedEvent, LoadBundleEvent, LogEntry, ModalityAddedEvent,
+    ProvenanceLog, PyEvent, Sys, SystemEnvironment, TransformEvent,
+};
+pub use format::{Format, FORMAT_NAMES};
+pub use hash::{derive_from_reader, hash_code_of, parse_hash_code, DEFAULT_HASH, HASH_NAMES};
+pub use locator::{ContentStore, Locator, PayloadContext, PayloadReader};
+use oca_sdk_rs::{OCABundle, OCABundleModel};
+pub use payload::{hash_file, hash_payload, PayloadCheck, PayloadReport, PayloadStatus};
+pub use prov::ProvFormat;
+use pyo3::exceptions::{PyTypeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict, PyString};
+use pyo3_polars::PyDataFrame;
+pub use resolve::{
+    resolve_bundle_id, verify_bundle, BundleResolver, BundleStore, DEFAULT_BUNDLE_STORE,
+};
 use said::derivation::HashFunctionCode;
-use said::{make_me_happy, SelfAddressingIdentifier};
+use said::SelfAddressingIdentifier;
+pub use semantics::{compile_ocafile, infer_bundle, infer_semantics};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+pub use store::{BlobStore, FsckReport};
+pub use transform::{Link, LINK_OVERLAY};
+pub use verify::{DigestFailure, VerificationReport};
+
+/// Version written into newly created MMIOs.
+pub const MMIO_VERSION: &str = "0.2";
+
+/// MMIO versions this implementation knows how to read and digest.
+pub const SUPPORTED_VERSIONS: &[&str] = &["0.1", "0.2"];
 
 #[pyclass]
 pub struct PySaid {
@@ -26,6 +80,19 @@ pub struct MMIO {
     pub version: String,
     pub digest: Option<SelfAddressingIdentifier>,
     pub modalities: Vec<Modality>,
+    /// Head of the provenance log recorded by [`MMIO::commit_provenance`],
+    /// committing the document to its history up to that event.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provenance: Option<SelfAddressingIdentifier>,
+    /// Events recorded by the mutating methods of this session. Not part of
+    /// the document.
+    #[serde(skip)]
+    pub log: ProvenanceLog,
+    /// Records ingested with [`MMIO::ingest`] or loaded with
+    /// [`MMIO::load_records`] in this session. The document only holds
+    /// their SAID.
+    #[serde(skip)]
+    pub data: Option<TabularData>,
 }
 
 #[pyclass(name = "Modality")]
@@ -36,29 +103,223 @@ pub struct Modality {
     pub modality_type: ModalityType,
     pub media_type: String,
     pub oca_bundle: Semantic,
+    /// Where the payload can be found. Not part of the digest.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub locator: Option<Locator>,
 }
 
 impl MMIO {
-    pub fn compute_digest(&mut self) {
-        self.version = "0.1".to_string();
-        let serialized = serde_json::to_string(self).unwrap();
-        println!("{}", serialized);
-        let code = HashFunctionCode::Blake3_256;
-        let field_name = Some("digest");
-        let computed = make_me_happy(&serialized, code, field_name).unwrap();
-        let mmio_wiht_digest = serde_json::from_str::<MMIO>(&computed).unwrap();
-        self.digest = Some(mmio_wiht_digest.digest.unwrap());
+    /// Parse an MMIO from its JSON representation, rejecting versions this
+    /// implementation does not support.
+    pub fn from_json(s: &str) -> Result<Self, MmioError> {
+        let mmio = serde_json::from_str::<MMIO>(s)?;
+        if !SUPPORTED_VERSIONS.contains(&mmio.version.as_str()) {
+            return Err(MmioError::UnsupportedVersion(mmio.version));
+        }
+        Ok(mmio)
+    }
+
+    /// Compute the SAID of the MMIO over its declared version and modalities
+    /// and store it in `digest`. Nothing else is modified. The serialized
+    /// input is emitted as a `tracing` debug event for diagnostics. See the
+    /// [`canonical`] module for how the declared version selects the
+    /// serialization that is hashed.
+    ///
+    /// The hash algorithm of an existing digest is kept, otherwise
+    /// [`DEFAULT_HASH`] is used.
+    pub fn compute_digest(&mut self) -> Result<SelfAddressingIdentifier, MmioError> {
+        let code = self.digest.as_ref().map_or(DEFAULT_HASH, hash_code_of);
+        self.compute_digest_with(code)
+    }
+
+    /// Compute the digest as [`MMIO::compute_digest`] does, with the given
+    /// hash algorithm.
+    pub fn compute_digest_with(
+        &mut self,
+        code: HashFunctionCode,
+    ) -> Result<SelfAddressingIdentifier, MmioError> {
+        let _span = tracing::debug_span!("mmio_digest", version = %self.version).entered();
+        let digest = canonical::derive_said(self, &code, &self.version)?;
+        self.digest = Some(digest.clone());
+        self.log
+            .add_event(Event::DigestComputed(DigestComputedEvent::new(
+                None,
+                digest.clone(),
+            )))?;
+        Ok(digest)
+    }
+
+    /// Recompute the digest of every modality and then of the MMIO, keeping
+    /// the hash algorithm of each existing digest.
+    pub fn compute_digests(&mut self) -> Result<SelfAddressingIdentifier, MmioError> {
+        for (i, modality) in self.modalities.iter_mut().enumerate() {
+            let code = modality.digest.as_ref().map_or(DEFAULT_HASH, hash_code_of);
+            let digest = modality.compute_digest_with(code, &self.version)?;
+            self.log
+                .add_event(Event::DigestComputed(DigestComputedEvent::new(
+                    Some(i),
+                    digest,
+                )))?;
+        }
+        self.compute_digest()
+    }
+
+    /// Append a modality and clear the now stale MMIO digest.
+    pub fn add_modality(&mut self, modality: Modality) -> Result<(), MmioError> {
+        self.log
+            .add_event(Event::ModalityAdded(ModalityAddedEvent::new(
+                self.modalities.len(),
+                modality.modality_said.clone(),
+                modality.media_type.clone(),
+            )))?;
+        self.modalities.push(modality);
+        self.digest = None;
+        Ok(())
+    }
+
+    /// Attach `semantic` to the modality at `index`, clearing the digests of
+    /// the modality and the MMIO.
+    pub fn set_semantic(&mut self, index: usize, semantic: Semantic) -> Result<(), MmioError> {
+        let modality = self
+            .modalities
+            .get_mut(index)
+            .ok_or_else(|| MmioError::InvalidModality(format!("no modality at index {}", index)))?;
+        self.log
+            .add_event(Event::LoadBundle(LoadBundleEvent::new(index, &semantic)))?;
+        modality.oca_bundle = semantic;
+        modality.digest = None;
+        self.digest = None;
+        Ok(())
+    }
+
+    /// Replace the modalities, clearing the MMIO digest. Changes are logged
+    /// the way [`MMIO::set_semantic`] and [`MMIO::add_modality`] log them:
+    /// a modality that differs from the one at its position only in its
+    /// semantic gets a `LoadBundleEvent`, any other new or changed modality
+    /// a `ModalityAddedEvent`.
+    pub fn replace_modalities(&mut self, modalities: Vec<Modality>) -> Result<(), MmioError> {
+        let previous = std::mem::take(&mut self.modalities);
+        self.digest = None;
+        for (index, modality) in modalities.into_iter().enumerate() {
+            let Some(old) = previous.get(index) else {
+                self.add_modality(modality)?;
+                continue;
+            };
+            let mut kept = modality.clone();
+            kept.oca_bundle = old.oca_bundle.clone();
+            if serde_json::to_value(&kept)? != serde_json::to_value(old)? {
+                self.add_modality(modality)?;
+                continue;
+            }
+            self.modalities.push(kept);
+            if serde_json::to_value(&modality.oca_bundle)? != serde_json::to_value(&old.oca_bundle)?
+            {
+                self.set_semantic(index, modality.oca_bundle)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Serialize the MMIO to compact JSON.
+    pub fn serialize(&self) -> Result<String, MmioError> {
+        Ok(serde_json::to_string(self)?)
+    }
+
+    /// Record the current head of the provenance log in the document and
+    /// recompute the MMIO digest over it.
+    pub fn commit_provenance(&mut self) -> Result<SelfAddressingIdentifier, MmioError> {
+        self.provenance = self.log.head().cloned();
+        self.compute_digest()
+    }
+
+    /// Check the provenance log chain and, if the document records a head,
+    /// that the log contains it.
+    pub fn verify_provenance(&self) -> Result<(), MmioError> {
+        match &self.provenance {
+            Some(head) => self.log.verify_head(head),
+            None => self.log.verify(),
+        }
     }
 }
 
+/// Explicit values for a modality created with [`Modality::from_reader`] or
+/// [`Modality::from_path`], taking precedence over what is inferred from the
+/// payload.
+#[derive(Clone, Debug, Default)]
+pub struct ModalityOptions {
+    pub media_type: Option<String>,
+    pub modality_type: Option<ModalityType>,
+    /// Hash algorithm for `modality_said` and `digest`, [`DEFAULT_HASH`] if
+    /// not given.
+    pub hash: Option<HashFunctionCode>,
+    pub locator: Option<Locator>,
+}
+
 impl Modality {
-    pub fn compute_digest(&mut self) {
-        let serialized = serde_json::to_string(self).unwrap();
-        let code = HashFunctionCode::Blake3_256;
-        let field_name = Some("digest");
-        let computed = make_me_happy(&serialized, code, field_name).unwrap();
-        let modality_wiht_digest = serde_json::from_str::<Modality>(&computed).unwrap();
-        self.digest = Some(modality_wiht_digest.digest.unwrap());
+    /// Create a modality from the content of the file at `path`. See
+    /// [`Modality::from_reader`].
+    pub fn from_path(
+        path: impl AsRef<Path>,
+        semantic: Semantic,
+        options: &ModalityOptions,
+    ) -> Result<Self, MmioError> {
+        let file = File::open(path)?;
+        Self::from_reader(file, semantic, options)
+    }
+
+    /// Create a modality whose payload is read from `reader`.
+    ///
+    /// The whole stream is hashed into `modality_said`. The media type is
+    /// inferred from the first bytes and the modality type from the media
+    /// type, unless `options` says otherwise. The digest is computed for an
+    /// MMIO of [`MMIO_VERSION`].
+    pub fn from_reader<R: Read>(
+        reader: R,
+        semantic: Semantic,
+        options: &ModalityOptions,
+    ) -> Result<Self, MmioError> {
+        let code = options.hash.clone().unwrap_or(DEFAULT_HASH);
+        let (said, sniffed) = hash_payload(reader, &code)?;
+        let media_type = options
+            .media_type
+            .clone()
+            .unwrap_or_else(|| sniffed.to_string());
+        let modality_type = options
+            .modality_type
+            .clone()
+            .unwrap_or_else(|| ModalityType::from_media_type(&media_type));
+
+        let mut modality = Modality {
+            digest: None,
+            modality_said: Some(said),
+            modality_type,
+            media_type,
+            oca_bundle: semantic,
+            locator: options.locator.clone(),
+        };
+        modality.compute_digest_with(code, MMIO_VERSION)?;
+        Ok(modality)
+    }
+
+    /// Compute the SAID of the modality with the serialization rules of the
+    /// version of the MMIO it belongs to, and store it in `digest`, keeping
+    /// the hash algorithm of an existing digest.
+    pub fn compute_digest(&mut self, version: &str) -> Result<SelfAddressingIdentifier, MmioError> {
+        let code = self.digest.as_ref().map_or(DEFAULT_HASH, hash_code_of);
+        self.compute_digest_with(code, version)
+    }
+
+    /// Compute the digest as [`Modality::compute_digest`] does, with the
+    /// given hash algorithm.
+    pub fn compute_digest_with(
+        &mut self,
+        code: HashFunctionCode,
+        version: &str,
+    ) -> Result<SelfAddressingIdentifier, MmioError> {
+        let _span = tracing::debug_span!("modality_digest", version).entered();
+        let digest = canonical::derive_said(self, &code, version)?;
+        self.digest = Some(digest.clone());
+        Ok(digest)
     }
 }
 
@@ -69,10 +330,11 @@ pub enum ModalityType {
     Audio,
     Video,
     Binary,
+    Tabular,
 }
 
 impl std::str::FromStr for ModalityType {
-    type Err = String;
+    type Err = MmioError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s.to_lowercase().as_str() {
@@ -81,10 +343,33 @@ impl std::str::FromStr for ModalityType {
             "audio" => Ok(ModalityType::Audio),
             "video" => Ok(ModalityType::Video),
             "binary" => Ok(ModalityType::Binary),
-            _ => Err(format!(
-                "Invalid modality type: '{}'. Valid types are: image, text, audio, video, binary",
+            "tabular" => Ok(ModalityType::Tabular),
+            _ => Err(MmioError::InvalidModality(format!(
+                "unknown modality type '{}'. Valid types are: image, text, audio, video, binary, tabular",
                 s
-            )),
+            ))),
+        }
+    }
+}
+
+impl ModalityType {
+    /// Modality type for a media type such as `image/png`. Unknown types
+    /// default to text.
+    pub fn from_media_type(media_type: &str) -> Self {
+        if media_type.starts_with("image/") {
+            ModalityType::Image
+        } else if media_type.starts_with("text/") || media_type == "application/json" {
+            ModalityType::Text
+        } else if media_type.starts_with("audio/") {
+            ModalityType::Audio
+        } else if media_type.starts_with("video/") {
+            ModalityType::Video
+        } else if media_type == ARROW_MEDIA_TYPE {
+            ModalityType::Tabular
+        } else if media_type.starts_with("application/") {
+            ModalityType::Binary
+        } else {
+            ModalityType::Text
         }
     }
 }
@@ -97,12 +382,14 @@ impl std::fmt::Display for ModalityType {
             ModalityType::Audio => write!(f, "audio"),
             ModalityType::Video => write!(f, "video"),
             ModalityType::Binary => write!(f, "binary"),
+            ModalityType::Tabular => write!(f, "tabular"),
         }
     }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(tag = "type", content = "value")]
+#[allow(clippy::large_enum_variant)]
 pub enum Semantic {
     Reference(SelfAddressingIdentifier),
     Bundle(OCABundleModel),
@@ -126,16 +413,123 @@ impl PyOCABundle {
     }
 }
 
+/// Parse an OCA bundle either in its published JSON form, with overlay
+/// properties inline, or in the model form embedded in MMIO documents, where
+/// each overlay carries `overlay_def` and `properties`.
+pub fn parse_oca_bundle(json: &str) -> Result<OCABundleModel, MmioError> {
+    let value: serde_json::Value = serde_json::from_str(json)?;
+    let model_form = value["overlays"]
+        .as_array()
+        .is_some_and(|overlays| overlays.iter().any(|o| o.get("overlay_def").is_some()));
+    if model_form {
+        return Ok(serde_json::from_value(value)?);
+    }
+    let bundle: OCABundle = serde_json::from_value(value)?;
+    Ok(OCABundleModel {
+        version: bundle.version,
+        digest: bundle.digest,
+        capture_base: bundle.capture_base,
+        overlays: bundle.overlays.into_iter().map(|o| o.model).collect(),
+        attributes: None,
+    })
+}
+
+/// SAID of the bundle a Python caller names by SAID or by a `name@version`
+/// alias in the bundle store at `store`, [`DEFAULT_BUNDLE_STORE`] if not
+/// given.
+pub(crate) fn bundle_id(
+    target: &str,
+    store: Option<PathBuf>,
+) -> Result<SelfAddressingIdentifier, MmioError> {
+    if !target.contains('@') {
+        return parse_said(target);
+    }
+    let store = BundleStore::open(store.unwrap_or_else(|| DEFAULT_BUNDLE_STORE.into()))?;
+    resolve_bundle_id(target, &store)
+}
+
+/// Bundle a Python caller names by SAID or by a `name@version` alias, read
+/// from the bundle store at `store`, [`DEFAULT_BUNDLE_STORE`] if not given.
+pub(crate) fn stored_bundle(
+    target: &str,
+    store: Option<PathBuf>,
+) -> Result<OCABundleModel, MmioError> {
+    let store = BundleStore::open(store.unwrap_or_else(|| DEFAULT_BUNDLE_STORE.into()))?;
+    let said = resolve_bundle_id(target, &store)?;
+    store.get(&said)?.ok_or_else(|| {
+        MmioError::InvalidSemantics(format!(
+            "bundle {} is not in the store at {}, add it first",
+            said,
+            store.root().display()
+        ))
+    })
+}
+
+pub(crate) fn parse_said(said: &str) -> Result<SelfAddressingIdentifier, MmioError> {
+    said.parse()
+        .map_err(|e: said::error::Error| MmioError::InvalidSaid(e.to_string()))
+}
+
 #[pymethods]
-impl PySemantic {
+impl PyOCABundle {
     #[staticmethod]
-    pub fn reference(said: String) -> Self {
-        let id: SelfAddressingIdentifier = said.parse().unwrap();
-        Self {
-            inner: Semantic::Reference(id),
+    pub fn from_json(json: &str) -> PyResult<Self> {
+        Ok(Self {
+            inner: parse_oca_bundle(json)?,
+        })
+    }
+
+    /// Compile OCAfile source into a bundle.
+    #[staticmethod]
+    #[pyo3(name = "from_ocafile")]
+    pub fn py_from_ocafile(source: &str) -> PyResult<Self> {
+        Ok(Self {
+            inner: compile_ocafile(source)?,
+        })
+    }
+
+    #[getter]
+    fn get_digest(&self) -> Option<String> {
+        self.inner.digest.as_ref().map(|said| said.to_string())
+    }
+
+    /// The bundle in the model form embedded in MMIO documents.
+    pub fn __str__(&self) -> PyResult<String> {
+        Ok(serde_json::to_string(&self.inner).map_err(MmioError::from)?)
+    }
+}
+
+#[pymethods]
+impl PySemantic {
+    /// Create a semantic from a bundle SAID or a `PyOCABundle`.
+    #[new]
+    fn py_new(value: &Bound<'_, PyAny>) -> PyResult<Self> {
+        if let Ok(bundle) = value.downcast::<PyOCABundle>() {
+            return Ok(Self::bundle(&bundle.borrow()));
+        }
+        match value.extract::<String>() {
+            Ok(said) => Self::reference(said),
+            Err(_) => Err(PyTypeError::new_err(
+                "expected a bundle SAID string or a PyOCABundle",
+            )),
         }
     }
 
+    #[staticmethod]
+    pub fn reference(said: String) -> PyResult<Self> {
+        Ok(Self {
+            inner: Semantic::Reference(parse_said(&said)?),
+        })
+    }
+
+    /// Compile OCAfile source into an embedded bundle.
+    #[staticmethod]
+    pub fn from_ocafile(source: &str) -> PyResult<Self> {
+        Ok(Self {
+            inner: Semantic::from_ocafile(source)?,
+        })
+    }
+
     #[staticmethod]
     pub fn bundle(bundle: &PyOCABundle) -> Self {
         Self {
@@ -151,12 +545,12 @@ impl PySemantic {
         matches!(self.inner, Semantic::Bundle(_))
     }
 
-    pub fn __str__(&self) -> String {
+    pub fn __str__(&self) -> PyResult<String> {
         match &self.inner {
-            Semantic::Reference(id) => id.to_string(),
+            Semantic::Reference(id) => Ok(id.to_string()),
             Semantic::Bundle(oca_bundle) => {
-                let oca_bundle = serde_json::to_string(&oca_bundle).unwrap();
-                oca_bundle.to_string()
+                let oca_bundle = serde_json::to_string(&oca_bundle).map_err(MmioError::from)?;
+                Ok(oca_bundle)
             }
         }
     }
@@ -165,15 +559,42 @@ impl PySemantic {
 impl MMIO {
     fn new() -> Self {
         Self {
-            version: "0.1".to_string(),
+            version: MMIO_VERSION.to_string(),
             modalities: vec![],
             digest: None,
+            provenance: None,
+            log: ProvenanceLog::new(),
+            data: None,
         }
     }
 }
 
 #[pymethods]
 impl Modality {
+    /// Create a modality without a digest. The modality type is derived from
+    /// the media type unless given.
+    #[new]
+    #[pyo3(signature = (media_type, oca_bundle, modality_type=None, modality_said=None))]
+    fn py_new(
+        media_type: String,
+        oca_bundle: PyRef<'_, PySemantic>,
+        modality_type: Option<&str>,
+        modality_said: Option<&str>,
+    ) -> PyResult<Self> {
+        let modality_type = match modality_type {
+            Some(t) => t.parse()?,
+            None => ModalityType::from_media_type(&media_type),
+        };
+        Ok(Modality {
+            digest: None,
+            modality_said: modality_said.map(parse_said).transpose()?,
+            modality_type,
+            media_type,
+            oca_bundle: oca_bundle.inner.clone(),
+            locator: None,
+        })
+    }
+
     #[getter]
     fn get_id(&self) -> PySaid {
         self.digest.clone().map_or_else(
@@ -208,90 +629,376 @@ impl Modality {
         self.media_type.clone()
     }
 
+    // Setters clear the digest, which no longer matches the content
+
+    #[setter]
+    fn set_modality_said(&mut self, said: Option<&str>) -> PyResult<()> {
+        self.modality_said = said.map(parse_said).transpose()?;
+        self.digest = None;
+        Ok(())
+    }
+
+    #[setter]
+    fn set_modality_type(&mut self, modality_type: &str) -> PyResult<()> {
+        self.modality_type = modality_type.parse()?;
+        self.digest = None;
+        Ok(())
+    }
+
+    #[setter]
+    fn set_media_type(&mut self, media_type: String) {
+        self.media_type = media_type;
+        self.digest = None;
+    }
+
+    #[setter]
+    fn set_oca_bundle(&mut self, oca_bundle: PyRef<'_, PySemantic>) {
+        self.oca_bundle = oca_bundle.inner.clone();
+        self.digest = None;
+    }
+
+    /// Recompute the modality digest for an MMIO of the given version,
+    /// such as `mmio.version`, optionally with a named hash algorithm such
+    /// as `sha2-256`.
+    #[pyo3(name = "compute_digest", signature = (version, hash=None))]
+    fn py_compute_digest(&mut self, version: &str, hash: Option<&str>) -> PyResult<String> {
+        let said = match hash {
+            Some(name) => self.compute_digest_with(parse_hash_code(name)?, version)?,
+            None => self.compute_digest(version)?,
+        };
+        Ok(said.to_string())
+    }
+
+    #[staticmethod]
+    #[pyo3(name = "builder")]
+    fn py_builder() -> ModalityBuilder {
+        Self::builder()
+    }
+
     #[getter]
     fn get_oca_bundle(&self) -> PySemantic {
-        match &self.oca_bundle {
-            Semantic::Reference(said) => PySemantic::reference(said.to_string()),
-            Semantic::Bundle(bundle) => PySemantic::bundle(&PyOCABundle {
-                inner: bundle.clone(),
-            }),
+        PySemantic {
+            inner: self.oca_bundle.clone(),
         }
     }
 }
 
 #[pymethods]
 impl MMIO {
+    #[staticmethod]
+    #[pyo3(name = "builder")]
+    fn py_builder() -> MmioBuilder {
+        Self::builder()
+    }
+
+    /// Create an MMIO of [`MMIO_VERSION`] without a digest, logging each
+    /// modality as added.
+    #[new]
+    #[pyo3(signature = (modalities=vec![]))]
+    fn py_new(modalities: Vec<Modality>) -> PyResult<Self> {
+        let mut mmio = Self::new();
+        for modality in modalities {
+            mmio.add_modality(modality)?;
+        }
+        Ok(mmio)
+    }
+
+    #[getter]
+    fn get_version(&self) -> String {
+        self.version.clone()
+    }
+
+    #[getter]
+    fn get_digest(&self) -> Option<String> {
+        self.digest.as_ref().map(|said| said.to_string())
+    }
+
     #[getter]
     fn get_modalities(&self) -> Vec<Modality> {
         self.modalities.clone()
     }
 
-    // fn ingest(&mut self, data: MMRecord) {
-    //     todo!()
-    // }
+    #[getter]
+    fn get_events(&self) -> Vec<PyEvent> {
+        self.log.events.iter().cloned().map(PyEvent::from).collect()
+    }
+
+    #[getter]
+    fn get_provenance(&self) -> Option<String> {
+        self.provenance.as_ref().map(|said| said.to_string())
+    }
+
+    #[pyo3(name = "add_modality")]
+    fn py_add_modality(&mut self, modality: Modality) -> PyResult<()> {
+        Ok(self.add_modality(modality)?)
+    }
+
+    /// The provenance log as JSON, to be saved next to the document.
+    fn log_json(&self) -> PyResult<String> {
+        Ok(self.log.to_json()?)
+    }
+
+    /// Replace the provenance log with a saved one after verifying it.
+    fn load_log(&mut self, json: &str) -> PyResult<()> {
+        self.log = ProvenanceLog::from_json(json)?;
+        Ok(())
+    }
+
+    #[pyo3(name = "commit_provenance")]
+    fn py_commit_provenance(&mut self) -> PyResult<String> {
+        Ok(self.commit_provenance()?.to_string())
+    }
+
+    #[pyo3(name = "verify_provenance")]
+    fn py_verify_provenance(&self) -> PyResult<()> {
+        Ok(self.verify_provenance()?)
+    }
+
+    /// The provenance log as W3C PROV, in `prov-json` or `ttl` (Turtle).
+    #[pyo3(name = "export_provenance", signature = (format="prov-json"))]
+    fn py_export_provenance(&self, format: &str) -> PyResult<String> {
+        Ok(self.export_provenance(format.parse()?)?)
+    }
+
+    /// Inline the bundles referenced by modalities from the bundle store in
+    /// directory `store`. Returns the number of modalities resolved.
+    #[pyo3(name = "resolve_semantics")]
+    fn py_resolve_semantics(&mut self, store: PathBuf) -> PyResult<usize> {
+        Ok(self.resolve_semantics(&BundleStore::open(store)?)?)
+    }
+
+    #[pyo3(name = "set_semantic")]
+    fn py_set_semantic(&mut self, index: usize, semantic: PyRef<'_, PySemantic>) -> PyResult<()> {
+        Ok(self.set_semantic(index, semantic.inner.clone())?)
+    }
+
+    /// Replace the modalities, clearing the MMIO digest and logging the
+    /// changes.
+    #[setter]
+    fn set_modalities(&mut self, modalities: Vec<Modality>) -> PyResult<()> {
+        Ok(self.replace_modalities(modalities)?)
+    }
 
-    fn serialize(&self) -> PyResult<String> {
-        // Serialize the MMIO to JSON
-        let json = serde_json::to_string(&self)
-            .map_err(|e| PyErr::new::<PyValueError, _>(format!("{}", e)))?;
-        Ok(json)
+    /// Validate a polars DataFrame against the semantics of the tabular
+    /// modality and append it to its records.
+    #[pyo3(name = "ingest")]
+    fn py_ingest(&mut self, data: PyDataFrame) -> PyResult<()> {
+        Ok(self.ingest(&data.0)?)
+    }
+
+    /// Link attributes of the tabular modality's bundle to those of the
+    /// bundle `target`, given as a dict of source to target attribute. The
+    /// target is a SAID or a `name@version` alias in the bundle store.
+    #[pyo3(name = "link", signature = (target, linkage, store=None))]
+    fn py_link(
+        &mut self,
+        target: &str,
+        linkage: &Bound<'_, PyDict>,
+        store: Option<PathBuf>,
+    ) -> PyResult<()> {
+        let attributes = linkage
+            .iter()
+            .map(|(source, target)| Ok((source.extract()?, target.extract()?)))
+            .collect::<PyResult<_>>()?;
+        let target = bundle_id(target, store)?;
+        Ok(self.link(&Link::new(target, attributes))?)
+    }
+
+    /// Link attributes as a transformation OCAfile, with `LINK ATTRIBUTE`
+    /// lines and a `target` meta comment, describes.
+    #[pyo3(name = "link_ocafile")]
+    fn py_link_ocafile(&mut self, source: &str) -> PyResult<()> {
+        Ok(self.link(&Link::from_ocafile(source)?)?)
+    }
+
+    /// A new MMIO with the records transformed along the links to the
+    /// bundle `target`, a SAID or a `name@version` alias of a bundle in the
+    /// bundle store.
+    #[pyo3(name = "transform", signature = (target, store=None))]
+    fn py_transform(&self, target: &str, store: Option<PathBuf>) -> PyResult<MMIO> {
+        Ok(self.transform(&stored_bundle(target, store)?)?)
+    }
+
+    /// Records ingested or loaded in this session, or `None`.
+    #[getter]
+    fn get_data(slf: &Bound<'_, Self>) -> Option<TabularData> {
+        Self::attached_data(slf)
+    }
+
+    /// Load the records the tabular modality commits to from the Arrow IPC
+    /// file at `path`, so that more can be ingested.
+    #[pyo3(name = "load_records")]
+    fn py_load_records(&mut self, path: PathBuf) -> PyResult<()> {
+        Ok(self.load_records(File::open(path)?)?)
+    }
+
+    /// Recompute the MMIO digest, optionally with a named hash algorithm
+    /// such as `sha2-256`.
+    #[pyo3(name = "compute_digest", signature = (hash=None))]
+    fn py_compute_digest(&mut self, hash: Option<&str>) -> PyResult<String> {
+        let said = match hash {
+            Some(name) => self.compute_digest_with(parse_hash_code(name)?)?,
+            None => self.compute_digest()?,
+        };
+        Ok(said.to_string())
+    }
+
+    /// Recompute the digest of every modality and then of the MMIO.
+    #[pyo3(name = "compute_digests")]
+    fn py_compute_digests(&mut self) -> PyResult<String> {
+        Ok(self.compute_digests()?.to_string())
+    }
+
+    #[pyo3(name = "verify")]
+    fn py_verify(&self) -> PyResult<VerificationReport> {
+        Ok(self.verify()?)
+    }
+
+    /// Check modality payloads either under `dir` or at the files given in
+    /// `paths`, keyed by modality index.
+    #[pyo3(name = "verify_payloads", signature = (dir=None, paths=None))]
+    fn py_verify_payloads(
+        &self,
+        dir: Option<PathBuf>,
+        paths: Option<BTreeMap<usize, PathBuf>>,
+    ) -> PyResult<PayloadReport> {
+        let report = match (dir, paths) {
+            (Some(dir), None) => self.verify_payloads_in_dir(&dir)?,
+            (None, Some(paths)) => self.verify_payloads(&paths)?,
+            _ => {
+                return Err(PyValueError::new_err(
+                    "exactly one of `dir` or `paths` must be given",
+                ))
+            }
+        };
+        Ok(report)
+    }
+
+    /// Serialize the MMIO, as a JSON string or as CBOR or MessagePack
+    /// bytes.
+    #[pyo3(name = "serialize", signature = (format="json"))]
+    fn py_serialize<'py>(&self, py: Python<'py>, format: &str) -> PyResult<Bound<'py, PyAny>> {
+        match format.parse()? {
+            Format::Json => Ok(PyString::new(py, &self.serialize()?).into_any()),
+            format => Ok(PyBytes::new(py, &self.to_bytes(format)?).into_any()),
+        }
     }
 }
 
 #[pymodule]
 fn m2io_tmp(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    let py = m.py();
+    m.add("MmioError", py.get_type::<exceptions::MmioError>())?;
+    m.add(
+        "SerializationError",
+        py.get_type::<exceptions::SerializationError>(),
+    )?;
+    m.add(
+        "SaidDerivationError",
+        py.get_type::<exceptions::SaidDerivationError>(),
+    )?;
+    m.add(
+        "SaidMismatchError",
+        py.get_type::<exceptions::SaidMismatchError>(),
+    )?;
+    m.add(
+        "UnsupportedVersionError",
+        py.get_type::<exceptions::UnsupportedVersionError>(),
+    )?;
+    m.add(
+        "InvalidModalityError",
+        py.get_type::<exceptions::InvalidModalityError>(),
+    )?;
+    m.add(
+        "InvalidSaidError",
+        py.get_type::<exceptions::InvalidSaidError>(),
+    )?;
+    m.add(
+        "UnsupportedHashError",
+        py.get_type::<exceptions::UnsupportedHashError>(),
+    )?;
+    m.add(
+        "InvalidProvenanceError",
+        py.get_type::<exceptions::InvalidProvenanceError>(),
+    )?;
+    m.add(
+        "InvalidSemanticsError",
+        py.get_type::<exceptions::InvalidSemanticsError>(),
+    )?;
+    m.add(
+        "InvalidOcafileError",
+        py.get_type::<exceptions::InvalidOcafileError>(),
+    )?;
+    m.add(
+        "InvalidRecordsError",
+        py.get_type::<exceptions::InvalidRecordsError>(),
+    )?;
+    m.add(
+        "InvalidArchiveError",
+        py.get_type::<exceptions::InvalidArchiveError>(),
+    )?;
+    m.add("DataError", py.get_type::<exceptions::DataError>())?;
+    m.add_class::<MMIO>()?;
+    m.add_class::<Modality>()?;
+    m.add_class::<PySemantic>()?;
+    m.add_class::<PyOCABundle>()?;
+    m.add_class::<PyEvent>()?;
+    m.add_class::<TabularData>()?;
+    m.add_class::<MmioBuilder>()?;
+    m.add_class::<ModalityBuilder>()?;
+    m.add_class::<VerificationReport>()?;
+    m.add_class::<DigestFailure>()?;
+    m.add_class::<PayloadReport>()?;
+
+    /// Set whether events record the user and host name. Applies to events
+    /// recorded from now on.
     #[pyfn(m)]
-    fn open(b: String) -> PyResult<MMIO> {
-        let mmio = serde_json::from_str::<MMIO>(&b)
-            .map_err(|e| PyErr::new::<PyValueError, _>(format!("{}", e)))?;
+    fn redact_user(redact: bool) {
+        set_environment_capture(SystemEnvironment {
+            redact_user: redact,
+        });
+    }
 
-        Ok(mmio)
+    /// Parse an MMIO from a JSON string or from bytes in any encoding,
+    /// detected unless `format` is given.
+    #[pyfn(m)]
+    #[pyo3(signature = (b, format=None))]
+    fn open(b: &Bound<'_, PyAny>, format: Option<&str>) -> PyResult<MMIO> {
+        let format = format.map(str::parse::<Format>).transpose()?;
+        if let Ok(json) = b.cast::<PyString>() {
+            let format = format.or(Some(Format::Json));
+            return Ok(MMIO::from_bytes(json.to_str()?.as_bytes(), format)?);
+        }
+        let bytes = b
+            .cast::<PyBytes>()
+            .map_err(|_| PyTypeError::new_err("expected the serialized MMIO as str or bytes"))?;
+        Ok(MMIO::from_bytes(bytes.as_bytes(), format)?)
+    }
+
+    /// Verify `bundle` and add it to the bundle store, optionally under a
+    /// `name@version` alias. Returns its SAID.
+    #[pyfn(m)]
+    #[pyo3(signature = (bundle, alias=None, store=None))]
+    fn add_bundle(
+        bundle: PyRef<'_, PyOCABundle>,
+        alias: Option<&str>,
+        store: Option<PathBuf>,
+    ) -> PyResult<String> {
+        let store = BundleStore::open(store.unwrap_or_else(|| DEFAULT_BUNDLE_STORE.into()))?;
+        let said = store.add(&bundle.inner)?;
+        if let Some(alias) = alias {
+            store.add_alias(alias, &said)?;
+        }
+        Ok(said.to_string())
     }
 
-    // #[pyfn(m)]
-    // fn infer_semantics(data: MMRecord) -> PyResult<MMIO> {
-    //     let mut oca = OCABox::new();
-    //     data.0.schema().iter_fields().for_each(|f| {
-    //         let mut attr = Attribute::new(f.name().to_string());
-    //         match f.data_type() {
-    //             DataType::Int64 => {
-    //                 attr.set_attribute_type(NestedAttrType::Value(
-    //                     AttributeType::Numeric,
-    //                 ));
-    //             }
-    //             DataType::Float64 => {
-    //                 attr.set_attribute_type(NestedAttrType::Value(
-    //                     AttributeType::Numeric,
-    //                 ));
-    //             }
-    //             DataType::String => {
-    //                 attr.set_attribute_type(NestedAttrType::Value(
-    //                     AttributeType::Text,
-    //                 ));
-    //             }
-    //             _ => {
-    //                 attr.set_attribute_type(NestedAttrType::Value(
-    //                     AttributeType::Text,
-    //                 ));
-    //             }
-    //         }
-    //         oca.add_attribute(attr);
-    //     });
-    //
-    //     let oca_bundle = oca.generate_bundle();
-    //     let modality = Modality {
-    //         id: None,
-    //         modality_said: None,
-    //         modality_type: ModalityType::Image,
-    //         media_type: "text".to_string(),
-    //         oca_bundle: Semantic::Bundle(oca_bundle),
-    //     };
-    //     let mmio = MMIO {
-    //         version:"0.1".to_string(),
-    //         id: None,
-    //         data: vec![modality], };
-    //     Ok(mmio)
-    // }
+    /// Infer an OCA bundle from a polars DataFrame and return an MMIO with
+    /// a tabular modality embedding it.
+    #[pyfn(m)]
+    #[pyo3(name = "infer_semantics", signature = (data, name=None))]
+    fn py_infer_semantics(data: PyDataFrame, name: Option<&str>) -> PyResult<MMIO> {
+        Ok(infer_semantics(&data.0, name)?)
+    }
 
     Ok(())
 }
@@ -302,14 +1009,30 @@ mod tests {
 
     use super::*;
 
+    /// Binary modality committing to the payload `said`, referencing a
+    /// bundle SAID derived with the same algorithm.
+    fn binary_modality(said: SelfAddressingIdentifier) -> Modality {
+        let bundle = HashFunction::from(hash_code_of(&said)).derive(b"bundle");
+        Modality {
+            digest: None,
+            modality_said: Some(said),
+            modality_type: ModalityType::Binary,
+            media_type: "application/octet-stream".to_string(),
+            oca_bundle: Semantic::Reference(bundle),
+            locator: None,
+        }
+    }
+
     #[test]
     fn test_create_new() {
         let mut mmio = MMIO::new();
-        assert_eq!(mmio.version, "0.1");
+        assert_eq!(mmio.version, MMIO_VERSION);
+        // Legacy test vector, digested with the version 0.1 serialization
+        mmio.version = "0.1".to_string();
         assert_eq!(mmio.modalities.len(), 0);
         assert_eq!(mmio.digest, None);
 
-        let oca_bundle_json = r#"{"v":"OCAS20JSON000320_","digest":"EHJ58dssK7HxXJjATIdMjXy2aoJpZRH7cIai5NXPQaZD","capture_base":{"digest":"EHZSMO2EFsXy8r5XogQ381-VmOiTUQYjV3WNkBfWYaCH","type":"capture_base/2.0.0","attributes":{"first_name":"Text","hgt":"Numeric","last_name":"Text","wgt":"Numeric"}},"overlays":{"character_encoding":{"digest":"EEDz_xTwN9P8BCZcU33OfFrO_lWIry9Jl1srE9leGbwF","capture_base":"EHZSMO2EFsXy8r5XogQ381-VmOiTUQYjV3WNkBfWYaCH","type":"overlay/character_encoding/2.0.0","attribute_character_encoding":{"first_name":"utf-8","hgt":"utf-8","last_name":"utf-8","wgt":"utf-8"}},"meta":[{"digest":"EP9iNoIrLu9w3YAMNW8FLWj5sP6VpOIoTIvDeC_6kvK0","capture_base":"EHZSMO2EFsXy8r5XogQ381-VmOiTUQYjV3WNkBfWYaCH","type":"overlay/meta/2.0.0","language":"eng","description":"Standard 1 Patient BMI","name":"Patient BMI"}]}}"#;
+        let oca_bundle_json = r#"{"v":"OCAS20JSON000320_","digest":"EHJ58dssK7HxXJjATIdMjXy2aoJpZRH7cIai5NXPQaZD","capture_base":{"digest":"EHZSMO2EFsXy8r5XogQ381-VmOiTUQYjV3WNkBfWYaCH","type":"capture_base/2.0.0","attributes":{"first_name":"Text","hgt":"Numeric","last_name":"Text","wgt":"Numeric"}},"overlays":[{"digest":"EEDz_xTwN9P8BCZcU33OfFrO_lWIry9Jl1srE9leGbwF","capture_base":"EHZSMO2EFsXy8r5XogQ381-VmOiTUQYjV3WNkBfWYaCH","type":"overlay/character_encoding/2.0.0","overlay_def":null,"properties":{"attribute_character_encoding":{"first_name":"utf-8","hgt":"utf-8","last_name":"utf-8","wgt":"utf-8"}}},{"digest":"EP9iNoIrLu9w3YAMNW8FLWj5sP6VpOIoTIvDeC_6kvK0","capture_base":"EHZSMO2EFsXy8r5XogQ381-VmOiTUQYjV3WNkBfWYaCH","type":"overlay/meta/2.0.0","overlay_def":null,"properties":{"description":"Standard 1 Patient BMI","language":"eng","name":"Patient BMI"}}]}"#;
         let oca_bundle: OCABundleModel = serde_json::from_str(oca_bundle_json).unwrap();
 
         let code = HashFunctionCode::Blake3_256;
@@ -333,21 +1056,30 @@ mod tests {
 
         let said = HashFunction::from(code.clone()).derive(&serde_json::to_vec(&json).unwrap());
 
-
         let mut modality = Modality {
             digest: None,
             modality_said: Some(said),
             modality_type: ModalityType::Image,
             media_type: "image/png".to_string(),
             oca_bundle: Semantic::Bundle(oca_bundle),
+            locator: None,
         };
-        modality.compute_digest();
+        modality
+            .compute_digest_with(code.clone(), &mmio.version)
+            .unwrap();
         mmio.modalities.push(modality);
 
-        mmio.compute_digest();
+        mmio.compute_digest().unwrap();
         let computed_digest = mmio.digest.as_ref();
 
-        assert_eq!(computed_digest, Some(&"EI-TaIVg2tmtXMdjAlogb5OnmaAsdhHVnGqfhDMk4mTM".parse().unwrap()));
+        assert_eq!(
+            computed_digest,
+            Some(
+                &"EDyYYsywdbczr7JQP6pluH_Z4brZShFhmP299piZF7Xc"
+                    .parse()
+                    .unwrap()
+            )
+        );
 
         println!("Serialized MMIO: {}", mmio.serialize().unwrap());
 
@@ -357,12 +1089,1181 @@ mod tests {
     }
 
     #[test]
-    fn test_deserialize() {
+    fn test_legacy_vector() {
+        // Version 0.1 document as originally written, with the digest in
+        // `id`, the modalities in `data` and the bundle overlays as a map
         let json = r#"{"version":"0.1","id":"EI-TaIVg2tmtXMdjAlogb5OnmaAsdhHVnGqfhDMk4mTM","data":[{"id":"EA_zIBLGGyzCo5ywVZz5asrtktgxR2dLRiegv6-wmC89","modality_said":"EK0GaxzGUPg54gPrUVOE1BkmZyRJXWXjGmQCeZCjSNeQ","modality_type":"Image","media_type":"image/png","oca_bundle":{"type":"Bundle","value":{"digest":"EHJ58dssK7HxXJjATIdMjXy2aoJpZRH7cIai5NXPQaZD","capture_base":{"digest":"EHZSMO2EFsXy8r5XogQ381-VmOiTUQYjV3WNkBfWYaCH","type":"capture_base/2.0.0","attributes":{"first_name":"Text","hgt":"Numeric","last_name":"Text","wgt":"Numeric"}},"overlays":{"character_encoding":{"digest":"EEDz_xTwN9P8BCZcU33OfFrO_lWIry9Jl1srE9leGbwF","capture_base":"EHZSMO2EFsXy8r5XogQ381-VmOiTUQYjV3WNkBfWYaCH","type":"overlay/character_encoding/2.0.0","attribute_character_encoding":{"first_name":"utf-8","hgt":"utf-8","last_name":"utf-8","wgt":"utf-8"}},"meta":[{"digest":"EP9iNoIrLu9w3YAMNW8FLWj5sP6VpOIoTIvDeC_6kvK0","capture_base":"EHZSMO2EFsXy8r5XogQ381-VmOiTUQYjV3WNkBfWYaCH","type":"overlay/meta/2.0.0","language":"eng","description":"Standard 1 Patient BMI","name":"Patient BMI"}]}}}}]}"#;
-        let mmio: MMIO = serde_json::from_str(json).unwrap();
+        let code = HashFunctionCode::Blake3_256;
+        let digest = legacy_said(json, &code).unwrap();
+        assert_eq!(
+            digest,
+            "EI-TaIVg2tmtXMdjAlogb5OnmaAsdhHVnGqfhDMk4mTM"
+                .parse()
+                .unwrap()
+        );
+
+        let document: serde_json::Value = serde_json::from_str(json).unwrap();
+        let modality = serde_json::to_string(&document["data"][0]).unwrap();
+        assert_eq!(
+            legacy_said(&modality, &code).unwrap(),
+            "EA_zIBLGGyzCo5ywVZz5asrtktgxR2dLRiegv6-wmC89"
+                .parse()
+                .unwrap()
+        );
+
+        // A tampered document no longer reproduces its digest
+        let tampered = json.replace("image/png", "image/jpeg");
+        assert_ne!(legacy_said(&tampered, &code).unwrap(), digest);
+    }
+
+    #[test]
+    fn test_deserialize() {
+        let json = r#"{"version":"0.1","digest":"EDyYYsywdbczr7JQP6pluH_Z4brZShFhmP299piZF7Xc","modalities":[{"digest":"EKcZpYiXZB1U95N4uqnK6rgKUP0IKbVCip5vIo_t5ttL","modality_said":"EK0GaxzGUPg54gPrUVOE1BkmZyRJXWXjGmQCeZCjSNeQ","modality_type":"Image","media_type":"image/png","oca_bundle":{"type":"Bundle","value":{"v":"OCAS20JSON000320_","digest":"EHJ58dssK7HxXJjATIdMjXy2aoJpZRH7cIai5NXPQaZD","capture_base":{"digest":"EHZSMO2EFsXy8r5XogQ381-VmOiTUQYjV3WNkBfWYaCH","type":"capture_base/2.0.0","attributes":{"first_name":"Text","hgt":"Numeric","last_name":"Text","wgt":"Numeric"}},"overlays":[{"digest":"EEDz_xTwN9P8BCZcU33OfFrO_lWIry9Jl1srE9leGbwF","capture_base":"EHZSMO2EFsXy8r5XogQ381-VmOiTUQYjV3WNkBfWYaCH","type":"overlay/character_encoding/2.0.0","overlay_def":null,"properties":{"attribute_character_encoding":{"first_name":"utf-8","hgt":"utf-8","last_name":"utf-8","wgt":"utf-8"}}},{"digest":"EP9iNoIrLu9w3YAMNW8FLWj5sP6VpOIoTIvDeC_6kvK0","capture_base":"EHZSMO2EFsXy8r5XogQ381-VmOiTUQYjV3WNkBfWYaCH","type":"overlay/meta/2.0.0","overlay_def":null,"properties":{"description":"Standard 1 Patient BMI","language":"eng","name":"Patient BMI"}}]}}}]}"#;
+        let mmio = MMIO::from_json(json).unwrap();
         assert_eq!(mmio.version, "0.1");
         assert_eq!(mmio.modalities.len(), 1);
         assert_eq!(mmio.modalities[0].media_type, "image/png");
-        assert_eq!(mmio.digest.as_ref(), Some(&"EI-TaIVg2tmtXMdjAlogb5OnmaAsdhHVnGqfhDMk4mTM".parse().unwrap()));
+        assert_eq!(
+            mmio.digest.as_ref(),
+            Some(
+                &"EDyYYsywdbczr7JQP6pluH_Z4brZShFhmP299piZF7Xc"
+                    .parse()
+                    .unwrap()
+            )
+        );
+
+        // Rehashing a modality keeps the rules of the version it was read under
+        let mut modality = mmio.modalities[0].clone();
+        let digest = modality.digest.clone().unwrap();
+        assert_eq!(modality.compute_digest(&mmio.version).unwrap(), digest);
+        assert_ne!(modality.compute_digest(MMIO_VERSION).unwrap(), digest);
+    }
+
+    #[test]
+    fn test_errors() {
+        let json = r#"{"version":"9.9","digest":null,"modalities":[]}"#;
+        assert!(matches!(
+            MMIO::from_json(json),
+            Err(MmioError::UnsupportedVersion(v)) if v == "9.9"
+        ));
+        assert!(matches!(
+            MMIO::from_json("not json"),
+            Err(MmioError::Serialization(_))
+        ));
+        assert!(matches!(
+            "hologram".parse::<ModalityType>(),
+            Err(MmioError::InvalidModality(_))
+        ));
+    }
+
+    #[test]
+    fn test_verify() {
+        let json = r#"{"version":"0.1","digest":"EDyYYsywdbczr7JQP6pluH_Z4brZShFhmP299piZF7Xc","modalities":[{"digest":"EKcZpYiXZB1U95N4uqnK6rgKUP0IKbVCip5vIo_t5ttL","modality_said":"EK0GaxzGUPg54gPrUVOE1BkmZyRJXWXjGmQCeZCjSNeQ","modality_type":"Image","media_type":"image/png","oca_bundle":{"type":"Reference","value":"EHJ58dssK7HxXJjATIdMjXy2aoJpZRH7cIai5NXPQaZD"}},{"digest":null,"modality_said":"EK0GaxzGUPg54gPrUVOE1BkmZyRJXWXjGmQCeZCjSNeQ","modality_type":"Text","media_type":"text/plain","oca_bundle":{"type":"Reference","value":"EHJ58dssK7HxXJjATIdMjXy2aoJpZRH7cIai5NXPQaZD"}}]}"#;
+        let mut mmio = MMIO::from_json(json).unwrap();
+
+        let report = mmio.verify().unwrap();
+        assert!(!report.is_valid());
+        assert_eq!(report.modalities.len(), 2);
+        assert_eq!(report.modalities[0].index, Some(0));
+        assert!(!report.modalities[0].is_missing());
+        assert_eq!(report.modalities[1].index, Some(1));
+        assert!(report.modalities[1].is_missing());
+        assert!(report.mmio.is_some());
+        assert!(matches!(
+            report.into_result(),
+            Err(MmioError::SaidMismatch { .. })
+        ));
+
+        mmio.compute_digests().unwrap();
+        let report = mmio.verify().unwrap();
+        assert!(report.is_valid());
+        assert_eq!(report.failures().count(), 0);
+    }
+
+    #[test]
+    fn test_verify_payloads() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let code = HashFunctionCode::Blake3_256;
+        let said_of = |data: &[u8]| HashFunction::from(code.clone()).derive(data);
+
+        std::fs::write(dir.join("image.png"), b"image bytes").unwrap();
+        let tampered = said_of(b"original text");
+        std::fs::write(dir.join(format!("{}.txt", tampered)), b"tampered text").unwrap();
+        std::fs::write(dir.join("stray.bin"), b"stray").unwrap();
+
+        let mut mmio = MMIO::new();
+        mmio.modalities = vec![
+            binary_modality(said_of(b"image bytes")),
+            binary_modality(tampered),
+            binary_modality(said_of(b"not on disk")),
+        ];
+
+        let report = mmio.verify_payloads_in_dir(dir).unwrap();
+        assert!(!report.is_valid());
+        assert_eq!(report.payloads[0].status, PayloadStatus::Verified);
+        assert!(matches!(
+            report.payloads[1].status,
+            PayloadStatus::Modified { .. }
+        ));
+        assert_eq!(report.payloads[2].status, PayloadStatus::Missing);
+        assert_eq!(report.unexpected, vec![dir.join("stray.bin")]);
+
+        let paths = BTreeMap::from([(0, dir.join("image.png"))]);
+        let report = mmio.verify_payloads(&paths).unwrap();
+        assert_eq!(report.payloads[0].status, PayloadStatus::Verified);
+        assert_eq!(report.payloads[1].status, PayloadStatus::Missing);
+
+        let paths = BTreeMap::from([(7, dir.join("image.png"))]);
+        assert!(matches!(
+            mmio.verify_payloads(&paths),
+            Err(MmioError::InvalidModality(_))
+        ));
+        let paths = BTreeMap::from([(0, dir.to_path_buf())]);
+        assert!(matches!(
+            mmio.verify_payloads(&paths),
+            Err(MmioError::InvalidModality(_))
+        ));
+
+        // Modalities without a modality_said have nothing to check
+        mmio.modalities = vec![binary_modality(said_of(b"image bytes"))];
+        mmio.modalities[0].modality_said = None;
+        let report = mmio.verify_payloads(&BTreeMap::new()).unwrap();
+        assert_eq!(report.payloads[0].status, PayloadStatus::NoSaid);
+        assert!(report.is_valid());
+        assert!(report.is_valid_allowing_legacy());
+    }
+
+    #[test]
+    fn test_digest_keeps_version() {
+        let mut mmio = MMIO::new();
+        let v1 = mmio.compute_digest().unwrap();
+
+        mmio.version = "0.3".to_string();
+        let v2 = mmio.compute_digest().unwrap();
+        assert_eq!(mmio.version, "0.3");
+        assert_eq!(mmio.digest, Some(v2.clone()));
+        assert_ne!(v1, v2);
+    }
+
+    #[test]
+    fn test_hash_algorithms() {
+        let data = vec![7u8; 200_000];
+        for name in HASH_NAMES {
+            let code = parse_hash_code(name).unwrap();
+            let streamed = derive_from_reader(&code, data.as_slice()).unwrap();
+            assert_eq!(streamed, HashFunction::from(code.clone()).derive(&data));
+            assert_eq!(hash_code_of(&streamed), code);
+        }
+        assert_eq!(
+            parse_hash_code("SHA256").unwrap(),
+            HashFunctionCode::SHA2_256
+        );
+        assert!(matches!(
+            parse_hash_code("md5"),
+            Err(MmioError::UnsupportedHash(_))
+        ));
+
+        let mut mmio = MMIO::new();
+        let said = mmio
+            .compute_digest_with(HashFunctionCode::SHA3_256)
+            .unwrap();
+        assert!(said.to_string().starts_with('H'));
+        // The algorithm is detected from the stored digest
+        assert_eq!(mmio.compute_digest().unwrap(), said);
+        assert!(mmio.verify().unwrap().is_valid());
+    }
+
+    #[test]
+    fn test_builder() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        // A PNG signature followed by enough data to exceed the sniffed header
+        let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+        png.extend(vec![1u8; 2000]);
+        std::fs::write(dir.join("image.png"), &png).unwrap();
+
+        let bundle: SelfAddressingIdentifier = "EHJ58dssK7HxXJjATIdMjXy2aoJpZRH7cIai5NXPQaZD"
+            .parse()
+            .unwrap();
+        let mmio = MMIO::builder()
+            .add_modality(
+                Modality::builder()
+                    .with_file(dir.join("image.png"))
+                    .with_semantic_reference(bundle.clone()),
+            )
+            .add_modality(
+                Modality::builder()
+                    .with_bytes(b"a,b\n1,2\n".to_vec(), "text/csv")
+                    .with_semantic_reference(bundle.clone())
+                    .with_hash(HashFunctionCode::SHA2_256),
+            )
+            .build()
+            .unwrap();
+
+        assert_eq!(mmio.version, MMIO_VERSION);
+        let image = &mmio.modalities[0];
+        assert_eq!(image.media_type, "image/png");
+        assert!(matches!(image.modality_type, ModalityType::Image));
+        assert_eq!(
+            image.modality_said,
+            Some(HashFunction::from(DEFAULT_HASH).derive(&png))
+        );
+        let text = &mmio.modalities[1];
+        assert!(matches!(text.modality_type, ModalityType::Text));
+        assert_eq!(
+            hash_code_of(text.digest.as_ref().unwrap()),
+            HashFunctionCode::SHA2_256
+        );
+        assert!(mmio.verify().unwrap().is_valid());
+        std::fs::write(dir.join("data.csv"), b"a,b\n1,2\n").unwrap();
+        assert!(mmio.verify_payloads_in_dir(dir).unwrap().is_valid());
+
+        assert!(matches!(
+            Modality::builder().with_file(dir.join("image.png")).build(),
+            Err(MmioError::InvalidModality(_))
+        ));
+        assert!(matches!(
+            Modality::builder()
+                .with_semantic_reference(bundle)
+                .with_file(dir.join("absent.png"))
+                .build(),
+            Err(MmioError::Io(_))
+        ));
+    }
+
+    #[test]
+    fn test_modality_from_reader() {
+        let bundle = Semantic::Reference(
+            "EHJ58dssK7HxXJjATIdMjXy2aoJpZRH7cIai5NXPQaZD"
+                .parse()
+                .unwrap(),
+        );
+        let data = [b"%PDF-1.7\n".as_slice(), &[0u8; 1000]].concat();
+
+        let modality =
+            Modality::from_reader(data.as_slice(), bundle.clone(), &Default::default()).unwrap();
+        assert_eq!(modality.media_type, "application/pdf");
+        assert!(matches!(modality.modality_type, ModalityType::Binary));
+        assert_eq!(
+            modality.modality_said,
+            Some(HashFunction::from(DEFAULT_HASH).derive(&data))
+        );
+        assert!(modality.digest.is_some());
+
+        let options = ModalityOptions {
+            media_type: Some("text/plain".to_string()),
+            modality_type: Some(ModalityType::Image),
+            hash: Some(HashFunctionCode::SHA3_256),
+            ..Default::default()
+        };
+        let modality = Modality::from_reader(data.as_slice(), bundle, &options).unwrap();
+        assert_eq!(modality.media_type, "text/plain");
+        assert!(matches!(modality.modality_type, ModalityType::Image));
+        assert_eq!(
+            modality.modality_said,
+            Some(HashFunction::from(HashFunctionCode::SHA3_256).derive(&data))
+        );
+    }
+
+    #[test]
+    fn test_legacy_payload_said() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let code = HashFunctionCode::Blake3_256;
+        let said_of = |data: &[u8]| HashFunction::from(code.clone()).derive(data);
+
+        let large: Vec<u8> = (0..2000u32).map(|i| i as u8).collect();
+        std::fs::write(dir.join("large.bin"), &large).unwrap();
+        let (said, _) = hash_payload(large.as_slice(), &code).unwrap();
+        assert_eq!(said, said_of(&large));
+
+        // Earlier CLI versions left the sniffed header out of the SAID
+        let legacy = said_of(&large[512..]);
+        let small_legacy = said_of(b"");
+        std::fs::write(dir.join(format!("{}.txt", small_legacy)), b"short").unwrap();
+
+        let mut mmio = MMIO::new();
+        mmio.modalities = vec![binary_modality(legacy), binary_modality(small_legacy)];
+
+        let report = mmio.verify_payloads_in_dir(dir).unwrap();
+        assert_eq!(report.payloads[0].status, PayloadStatus::LegacySaid);
+        assert_eq!(report.payloads[0].path, Some(dir.join("large.bin")));
+        assert_eq!(report.payloads[1].status, PayloadStatus::LegacySaid);
+        assert!(!report.is_valid());
+        assert!(report.is_valid_allowing_legacy());
+
+        let paths = BTreeMap::from([(0, dir.join("large.bin"))]);
+        let report = mmio.verify_payloads(&paths).unwrap();
+        assert_eq!(report.payloads[0].status, PayloadStatus::LegacySaid);
+
+        mmio.modalities[0].modality_said = Some(said_of(b"something else"));
+        let report = mmio.verify_payloads(&paths).unwrap();
+        assert!(matches!(
+            report.payloads[0].status,
+            PayloadStatus::Modified { .. }
+        ));
+    }
+
+    #[test]
+    fn test_parse_oca_bundle() {
+        let wire = r#"{"v":"OCAS20JSON000320_","digest":"EHJ58dssK7HxXJjATIdMjXy2aoJpZRH7cIai5NXPQaZD","capture_base":{"digest":"EHZSMO2EFsXy8r5XogQ381-VmOiTUQYjV3WNkBfWYaCH","type":"capture_base/2.0.0","attributes":{"first_name":"Text"}},"overlays":[{"digest":"EP9iNoIrLu9w3YAMNW8FLWj5sP6VpOIoTIvDeC_6kvK0","capture_base":"EHZSMO2EFsXy8r5XogQ381-VmOiTUQYjV3WNkBfWYaCH","type":"overlay/meta/2.0.0","language":"eng","name":"Patient BMI"}]}"#;
+        let bundle = parse_oca_bundle(wire).unwrap();
+        let properties = bundle.overlays[0].properties.as_ref().unwrap();
+        assert_eq!(properties.len(), 2);
+        assert!(properties.contains_key("name"));
+
+        // The model form round-trips unchanged
+        let model = serde_json::to_string(&bundle).unwrap();
+        let reparsed = parse_oca_bundle(&model).unwrap();
+        assert_eq!(serde_json::to_string(&reparsed).unwrap(), model);
+
+        assert!(matches!(
+            parse_oca_bundle("{}"),
+            Err(MmioError::Serialization(_))
+        ));
+    }
+
+    #[test]
+    fn test_environment_capture() {
+        let sys = SystemEnvironment { redact_user: true }.capture();
+        assert_eq!(sys.user, None);
+        assert_eq!(sys.hostname, None);
+        assert_eq!(sys.m2io_version, env!("M2IO_VERSION"));
+        assert!(sys.os.starts_with(std::env::consts::OS));
+        assert_eq!(sys.python_version, None);
+
+        // Events recorded before the capture was extended
+        let old: Sys = serde_json::from_str(r#"{"user":"alice","version":"3.12.1"}"#).unwrap();
+        assert_eq!(old.user.as_deref(), Some("alice"));
+        assert_eq!(old.python_version.as_deref(), Some("3.12.1"));
+    }
+
+    #[test]
+    fn test_provenance_log() {
+        let bundle: SelfAddressingIdentifier = "EHJ58dssK7HxXJjATIdMjXy2aoJpZRH7cIai5NXPQaZD"
+            .parse()
+            .unwrap();
+        let mut mmio = MMIO::builder()
+            .add_modality(
+                Modality::builder()
+                    .with_bytes(b"a,b\n1,2\n".to_vec(), "text/csv")
+                    .with_semantic_reference(bundle.clone()),
+            )
+            .build()
+            .unwrap();
+        let types: Vec<_> = mmio
+            .log
+            .events
+            .iter()
+            .map(|e| e.event.get_event_type())
+            .collect();
+        assert_eq!(types, ["ModalityAddedEvent", "DigestComputedEvent"]);
+
+        let other = HashFunction::from(DEFAULT_HASH).derive(b"other bundle");
+        mmio.set_semantic(0, Semantic::Reference(other.clone()))
+            .unwrap();
+        assert_eq!(mmio.digest, None);
+        assert_eq!(mmio.modalities[0].digest, None);
+        assert!(matches!(
+            mmio.set_semantic(3, Semantic::Reference(other.clone())),
+            Err(MmioError::InvalidModality(_))
+        ));
+        mmio.compute_digests().unwrap();
+        assert!(mmio.verify().unwrap().is_valid());
+
+        let Event::LoadBundle(load) = &mmio.log.events[2].event else {
+            panic!("expected a LoadBundleEvent");
+        };
+        assert_eq!(load.bundle, Some(other));
+        let Event::DigestComputed(modality_digest) = &mmio.log.events[3].event else {
+            panic!("expected a DigestComputedEvent");
+        };
+        assert_eq!(modality_digest.index, Some(0));
+        assert_eq!(mmio.log.len(), 5);
+
+        // The log is not part of the document
+        let json = serde_json::to_string(&mmio).unwrap();
+        assert!(MMIO::from_json(&json).unwrap().log.is_empty());
+
+        let log_json = serde_json::to_string(&mmio.log).unwrap();
+        let log: ProvenanceLog = serde_json::from_str(&log_json).unwrap();
+        assert_eq!(log.len(), 5);
+        assert_eq!(log.events[2].event.time(), mmio.log.events[2].event.time());
+
+        // Python constructors and setters log through the same mutators
+        let created = MMIO::py_new(mmio.modalities.clone()).unwrap();
+        assert_eq!(created.log.len(), 1);
+        assert!(matches!(
+            created.log.events[0].event,
+            Event::ModalityAdded(_)
+        ));
+        let mut modalities = mmio.modalities.clone();
+        mmio.set_modalities(modalities.clone()).unwrap();
+        assert_eq!(mmio.log.len(), 5);
+        modalities[0].oca_bundle = Semantic::Reference(bundle);
+        modalities.push(modalities[0].clone());
+        mmio.set_modalities(modalities).unwrap();
+        let types: Vec<_> = mmio.log.events[5..]
+            .iter()
+            .map(|e| e.event.get_event_type())
+            .collect();
+        assert_eq!(types, ["LoadBundleEvent", "ModalityAddedEvent"]);
+        assert_eq!(mmio.modalities.len(), 2);
+        assert_eq!(mmio.modalities[0].digest, None);
+        assert_eq!(mmio.digest, None);
+    }
+
+    #[test]
+    fn test_provenance_chain() {
+        let bundle = Semantic::Reference(HashFunction::from(DEFAULT_HASH).derive(b"bundle"));
+        let mut mmio = MMIO::new();
+        for media_type in ["text/csv", "image/png", "audio/wav"] {
+            let options = ModalityOptions {
+                media_type: Some(media_type.to_string()),
+                ..Default::default()
+            };
+            let modality =
+                Modality::from_reader(media_type.as_bytes(), bundle.clone(), &options).unwrap();
+            mmio.add_modality(modality).unwrap();
+        }
+        mmio.commit_provenance().unwrap();
+        assert_eq!(mmio.provenance.as_ref(), mmio.log.events[2].digest.as_ref());
+        assert!(mmio.verify_provenance().is_ok());
+        assert!(mmio.verify().unwrap().is_valid());
+
+        // The recorded head survives a round trip of the document
+        let reloaded = MMIO::from_json(&serde_json::to_string(&mmio).unwrap()).unwrap();
+        assert_eq!(reloaded.provenance, mmio.provenance);
+        assert!(reloaded.verify().unwrap().is_valid());
+
+        let tampered = |f: &dyn Fn(&mut ProvenanceLog)| {
+            let mut mmio = mmio.clone();
+            f(&mut mmio.log);
+            mmio.verify_provenance()
+        };
+        let broken = [
+            tampered(&|log| log.events.swap(1, 2)),
+            tampered(&|log| {
+                log.events.remove(1);
+            }),
+            tampered(&|log| {
+                log.events.remove(0);
+            }),
+            tampered(&|log| {
+                if let Event::ModalityAdded(e) = &mut log.events[1].event {
+                    e.media_type = "video/mp4".to_string();
+                }
+            }),
+            // Dropping entries up to the recorded head
+            tampered(&|log| log.events.truncate(2)),
+        ];
+        for result in broken {
+            assert!(matches!(result, Err(MmioError::InvalidProvenance(_))));
+        }
+        // Entries after the recorded head are not committed to
+        assert!(tampered(&|log| log.events.truncate(3)).is_ok());
+    }
+
+    #[test]
+    fn test_feed_event_round_trip() {
+        use polars::prelude::*;
+
+        let data = df!(
+            "first_name" => ["John", "Jane"],
+            "hgt" => [180i64, 165],
+            "wgt" => [75.5f64, 60.0],
+        )
+        .unwrap();
+        let mut log = ProvenanceLog::new();
+        log.add_event(Event::Feed(FeedEvent::new(&data, true).unwrap()))
+            .unwrap();
+        log.add_event(Event::Feed(FeedEvent::new(&data, false).unwrap()))
+            .unwrap();
+
+        let reloaded = ProvenanceLog::from_json(&log.to_json().unwrap()).unwrap();
+        assert_eq!(reloaded.head(), log.head());
+        let (Event::Feed(inline), Event::Feed(original)) =
+            (&reloaded.events[0].event, &log.events[0].event)
+        else {
+            panic!("expected feed events");
+        };
+        assert_eq!(inline.time, original.time);
+        assert_eq!(inline.data, original.data);
+        assert_eq!(inline.data.rows, 2);
+        assert_eq!(inline.data.schema, schema_fingerprint(&data).unwrap());
+        assert!(inline.records().unwrap().unwrap().equals(&data));
+
+        let Event::Feed(by_reference) = &reloaded.events[1].event else {
+            panic!("expected a feed event");
+        };
+        assert_eq!(by_reference.data, inline.data);
+        assert!(by_reference.records().unwrap().is_none());
+
+        let mut tampered = inline.clone();
+        tampered.payload = Some(DataReference::of(&data.head(Some(1))).unwrap().1);
+        assert!(matches!(
+            tampered.records(),
+            Err(MmioError::SaidMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_data_reference_chunks() {
+        use polars::prelude::*;
+
+        let first = df!("name" => ["Deirdre"], "height" => [174i64]).unwrap();
+        let second = df!("name" => ["Luis"], "height" => [181i64]).unwrap();
+        let chunked = first.vstack(&second).unwrap();
+        assert_eq!(chunked.first_col_n_chunks(), 2);
+        let mut single = chunked.clone();
+        single.as_single_chunk();
+        assert_eq!(single.first_col_n_chunks(), 1);
+
+        let (reference, ipc) = DataReference::of(&chunked).unwrap();
+        assert_eq!(reference, DataReference::of(&single).unwrap().0);
+        assert_eq!(ipc, TabularData::new(0, chunked).to_ipc().unwrap());
+    }
+
+    #[test]
+    fn test_export_provenance() {
+        use polars::prelude::*;
+
+        let mut mmio = MMIO::builder()
+            .add_modality(
+                Modality::builder()
+                    .with_bytes(b"a,b\n1,2\n".to_vec(), "text/csv")
+                    .with_semantic_reference(
+                        "EHJ58dssK7HxXJjATIdMjXy2aoJpZRH7cIai5NXPQaZD"
+                            .parse()
+                            .unwrap(),
+                    ),
+            )
+            .build()
+            .unwrap();
+        let bundle = HashFunction::from(DEFAULT_HASH).derive(b"bundle");
+        mmio.set_semantic(0, Semantic::Reference(bundle.clone()))
+            .unwrap();
+        let data = df!("a" => [1i64]).unwrap();
+        mmio.log
+            .add_event(Event::Feed(FeedEvent::new(&data, false).unwrap()))
+            .unwrap();
+        mmio.log
+            .add_event(Event::Transform(TransformEvent::new()))
+            .unwrap();
+        assert!(matches!(
+            mmio.export_provenance(ProvFormat::Json),
+            Err(MmioError::InvalidProvenance(_))
+        ));
+        mmio.compute_digests().unwrap();
+
+        let mmio_id = format!("said:{}", mmio.digest.as_ref().unwrap());
+        let modality_id = format!("said:{}", mmio.modalities[0].digest.as_ref().unwrap());
+        let load_id = format!("said:{}", mmio.log.events[2].digest.as_ref().unwrap());
+        let doc: serde_json::Value =
+            serde_json::from_str(&mmio.export_provenance(ProvFormat::Json).unwrap()).unwrap();
+        let entities = doc["entity"].as_object().unwrap();
+        assert!(entities.contains_key(&mmio_id));
+        assert!(entities.contains_key(&modality_id));
+        assert!(entities.contains_key(&format!("said:{}", bundle)));
+        assert_eq!(entities.len(), 4);
+        assert_eq!(doc["activity"].as_object().unwrap().len(), 3);
+        assert!(doc["agent"]
+            .as_object()
+            .unwrap()
+            .keys()
+            .any(|id| id.starts_with("m2io:software-")));
+        assert!(doc["wasInfluencedBy"]
+            .as_object()
+            .unwrap()
+            .values()
+            .any(|i| i["prov:influencee"] == modality_id.as_str()
+                && i["prov:influencer"] == load_id.as_str()));
+        // Only one activity may generate an entity
+        let generated: Vec<_> = doc
+            .get("wasGeneratedBy")
+            .and_then(|g| g.as_object())
+            .into_iter()
+            .flat_map(|g| g.values())
+            .map(|g| g["prov:entity"].clone())
+            .collect();
+        assert!(!generated.contains(&serde_json::json!(mmio_id)));
+        assert!(!generated.contains(&serde_json::json!(modality_id)));
+        for activity in doc["activity"].as_object().unwrap().values() {
+            assert!(activity.get("prov:startTime").is_some());
+            assert!(activity.get("prov:endTime").is_none());
+        }
+
+        let turtle = mmio.export_provenance(ProvFormat::Turtle).unwrap();
+        assert!(turtle.contains(&format!(
+            "{} a prov:Entity, prov:Collection, m2io:MMIO",
+            mmio_id
+        )));
+        assert!(turtle.contains(&format!("prov:used said:{}", bundle)));
+        assert!(turtle.contains(&format!("prov:wasInfluencedBy {}", load_id)));
+        assert!(!turtle.contains("prov:endedAtTime"));
+        assert!("ttl".parse::<ProvFormat>().is_ok());
+        assert!("xml".parse::<ProvFormat>().is_err());
+    }
+
+    #[test]
+    fn test_ingest() {
+        use polars::prelude::*;
+
+        let wire = r#"{"v":"OCAS20JSON000320_","digest":"EHJ58dssK7HxXJjATIdMjXy2aoJpZRH7cIai5NXPQaZD","capture_base":{"digest":"EHZSMO2EFsXy8r5XogQ381-VmOiTUQYjV3WNkBfWYaCH","type":"capture_base/2.0.0","attributes":{"first_name":"Text","hgt":"Numeric","wgt":"Numeric","smoker":"Boolean"}},"overlays":[]}"#;
+        let new_mmio = || {
+            let mut mmio = MMIO::new();
+            mmio.add_modality(Modality {
+                digest: None,
+                modality_said: None,
+                modality_type: ModalityType::Tabular,
+                media_type: ARROW_MEDIA_TYPE.to_string(),
+                oca_bundle: Semantic::Bundle(parse_oca_bundle(wire).unwrap()),
+                locator: None,
+            })
+            .unwrap();
+            mmio
+        };
+        let first = df!(
+            "first_name" => ["John", "Jane"],
+            "hgt" => [180i64, 165],
+            "wgt" => [75.5f64, 60.0],
+        )
+        .unwrap();
+        let second = df!(
+            "wgt" => [82.0f64],
+            "first_name" => ["Jim"],
+            "hgt" => [190i64],
+        )
+        .unwrap();
+
+        let mut mmio = new_mmio();
+        mmio.ingest(&first).unwrap();
+        mmio.ingest(&second).unwrap();
+        let data = mmio.data.as_ref().unwrap();
+        assert_eq!(data.records.height(), 3);
+        assert_eq!(
+            data.records.get_column_names_str(),
+            ["first_name", "hgt", "wgt"]
+        );
+        let said = mmio.modalities[0].modality_said.clone().unwrap();
+        assert_eq!(
+            said,
+            HashFunction::from(DEFAULT_HASH).derive(&data.to_ipc().unwrap())
+        );
+        assert_eq!(mmio.modalities[0].media_type, ARROW_MEDIA_TYPE);
+        let feeds = mmio
+            .log
+            .events
+            .iter()
+            .filter(|e| matches!(e.event, Event::Feed(_)))
+            .count();
+        assert_eq!(feeds, 2);
+
+        // The SAID depends on the records, not on how they were batched
+        let mut once = new_mmio();
+        once.ingest(
+            &first
+                .vstack(&second.select(["first_name", "hgt", "wgt"]).unwrap())
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(once.modalities[0].modality_said, Some(said.clone()));
+
+        let unknown = df!("first_name" => ["Ann"], "age" => [40i64]).unwrap();
+        let mistyped = df!("first_name" => ["Ann"], "hgt" => ["tall"], "wgt" => [50.0f64]).unwrap();
+        let missing = df!("first_name" => ["Ann"]).unwrap();
+        for data in [unknown, mistyped, missing] {
+            assert!(matches!(
+                mmio.ingest(&data),
+                Err(MmioError::InvalidRecords(_))
+            ));
+        }
+        assert_eq!(mmio.data.as_ref().unwrap().records.height(), 3);
+        // Attributes without a column are fine on the first batch
+        new_mmio()
+            .ingest(&df!("first_name" => ["Ann"]).unwrap())
+            .unwrap();
+
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let path = dir.join("records.arrow");
+        mmio.data.as_ref().unwrap().save(&path).unwrap();
+        let report = mmio.verify_payloads(&[(0, path.clone())].into()).unwrap();
+        assert!(report.is_valid());
+
+        // A reloaded document must load its records before stacking more
+        let third = df!("first_name" => ["Ann"], "hgt" => [170i64], "wgt" => [58.0f64]).unwrap();
+        let mut reloaded = MMIO::from_json(&mmio.serialize().unwrap()).unwrap();
+        assert!(reloaded.data.is_none());
+        assert!(matches!(
+            reloaded.ingest(&third),
+            Err(MmioError::InvalidModality(_))
+        ));
+        assert!(matches!(
+            reloaded.load_records(b"not arrow".as_slice()),
+            Err(MmioError::SaidMismatch { .. })
+        ));
+        reloaded
+            .load_records(std::fs::File::open(&path).unwrap())
+            .unwrap();
+        reloaded.ingest(&third).unwrap();
+        mmio.ingest(&third).unwrap();
+        assert_eq!(reloaded.data.as_ref().unwrap().records.height(), 4);
+        assert_eq!(
+            reloaded.modalities[0].modality_said,
+            mmio.modalities[0].modality_said
+        );
+
+        let mut referenced = MMIO::new();
+        referenced.modalities = vec![Modality {
+            oca_bundle: Semantic::Reference(said),
+            locator: None,
+            ..mmio.modalities[0].clone()
+        }];
+        assert!(matches!(
+            referenced.ingest(&first),
+            Err(MmioError::InvalidModality(_))
+        ));
+        assert!(matches!(
+            MMIO::new().ingest(&first),
+            Err(MmioError::InvalidModality(_))
+        ));
+    }
+
+    #[test]
+    fn test_infer_semantics() {
+        use oca_sdk_rs::{AttributeType, NestedAttrType};
+        use polars::prelude::*;
+
+        let data = df!(
+            "name" => ["Ann", "Bob", "Cid", "Dee"],
+            "hgt" => [170i64, 180, 175, 160],
+            "wgt" => [60.5f64, 80.0, 72.5, 55.0],
+            "smoker" => ["true", "false", "FALSE", "true"],
+            "visit" => ["2024-01-05", "2024-02-11", "2024-03-02", "2024-03-09"],
+            "site" => ["north", "south", "north", "north"],
+            "tags" => [Series::new("".into(), [1i64]), Series::new("".into(), [2i64, 3]), Series::new("".into(), [4i64]), Series::new("".into(), [5i64])],
+        )
+        .unwrap();
+
+        let bundle = infer_bundle(&data, Some("Visits")).unwrap();
+        assert!(bundle.digest.is_some());
+        let attributes = &bundle.capture_base.attributes;
+        let value = NestedAttrType::Value;
+        assert_eq!(attributes["name"], value(AttributeType::Text));
+        assert_eq!(attributes["hgt"], value(AttributeType::Numeric));
+        assert_eq!(attributes["wgt"], value(AttributeType::Numeric));
+        assert_eq!(attributes["smoker"], value(AttributeType::Boolean));
+        assert_eq!(attributes["visit"], value(AttributeType::DateTime));
+        assert_eq!(attributes["site"], value(AttributeType::Text));
+        assert_eq!(
+            attributes["tags"],
+            NestedAttrType::Array(Box::new(value(AttributeType::Numeric)))
+        );
+        let names: Vec<_> = bundle.overlays.iter().map(|o| o.name.as_str()).collect();
+        assert_eq!(names.len(), 3);
+        for name in ["meta", "character_encoding", "entry_code"] {
+            assert!(names.contains(&name), "no {} overlay in {:?}", name, names);
+        }
+        let entry_code = bundle
+            .overlays
+            .iter()
+            .find(|o| o.name == "entry_code")
+            .unwrap();
+        let codes = serde_json::to_value(&entry_code.properties).unwrap();
+        assert_eq!(
+            codes["attribute_entry_codes"],
+            serde_json::json!({ "site": ["north", "south"] })
+        );
+
+        let mut mmio = infer_semantics(&data, Some("Visits")).unwrap();
+        assert!(mmio.verify().unwrap().is_valid());
+        assert!(matches!(
+            mmio.modalities[0].modality_type,
+            ModalityType::Tabular
+        ));
+        assert!(matches!(
+            &mmio.modalities[0].oca_bundle,
+            Semantic::Bundle(b) if b.digest == bundle.digest
+        ));
+        mmio.ingest(&data).unwrap();
+        let not_a_date = df!("visit" => ["soon"]).unwrap();
+        assert!(matches!(
+            mmio.ingest(&not_a_date),
+            Err(MmioError::InvalidRecords(_))
+        ));
+    }
+
+    fn bundle_digest(mmio: &MMIO) -> Option<SelfAddressingIdentifier> {
+        match &mmio.modalities[0].oca_bundle {
+            Semantic::Bundle(bundle) => bundle.digest.clone(),
+            Semantic::Reference(said) => Some(said.clone()),
+        }
+    }
+
+    #[test]
+    fn test_transform() {
+        use polars::prelude::*;
+
+        let records = df!(
+            "name" => ["Deirdre", "Luis", "John"],
+            "surname" => ["Patterson", "Hembree", "Doe"],
+            "height" => [174i64, 181, 201],
+            "weight" => [68i64, 79, 98],
+        )
+        .unwrap();
+        let standard1 = compile_ocafile(
+            "-- name=Standard1\n\nADD ATTRIBUTE full_name=Text hgt=Numeric wgt=Numeric\n",
+        )
+        .unwrap();
+        let standard2 =
+            compile_ocafile("-- name=Standard2\n\nADD ATTRIBUTE first_name=Text hgt=Numeric\n")
+                .unwrap();
+        let standard = standard1.digest.clone().unwrap();
+        let other = standard2.digest.clone().unwrap();
+        let mut mmio = infer_semantics(&records, None).unwrap();
+        mmio.ingest(&records).unwrap();
+        let linkage = |pairs: &[(&str, &str)]| {
+            pairs
+                .iter()
+                .map(|(s, t)| (s.to_string(), t.to_string()))
+                .collect::<Vec<_>>()
+        };
+        let link = Link::new(
+            standard.clone(),
+            linkage(&[
+                ("name", "full_name"),
+                ("surname", "full_name"),
+                ("height", "hgt"),
+                ("weight", "wgt"),
+            ]),
+        );
+        mmio.link(&link).unwrap();
+        let Semantic::Bundle(bundle) = &mmio.modalities[0].oca_bundle else {
+            panic!("linking keeps the bundle embedded");
+        };
+        assert_eq!(Link::in_bundle(bundle).unwrap(), vec![link]);
+        mmio.link(&Link::new(
+            other.clone(),
+            linkage(&[("name", "first_name"), ("height", "hgt")]),
+        ))
+        .unwrap();
+        mmio.compute_digests().unwrap();
+
+        let transformed = mmio.transform(&standard1).unwrap();
+        let data = transformed.data.as_ref().unwrap();
+        let expected = df!(
+            "full_name" => ["Deirdre Patterson", "Luis Hembree", "John Doe"],
+            "hgt" => [174i64, 181, 201],
+            "wgt" => [68i64, 79, 98],
+        )
+        .unwrap();
+        assert!(data.records.equals(&expected));
+        let modality = &transformed.modalities[0];
+        assert!(matches!(&modality.oca_bundle, Semantic::Reference(s) if *s == standard));
+        assert_eq!(
+            modality.modality_said,
+            Some(HashFunction::from(DEFAULT_HASH).derive(&data.to_ipc().unwrap()))
+        );
+        assert!(transformed.verify().unwrap().is_valid());
+        transformed.verify_provenance().unwrap();
+        let event = transformed
+            .log
+            .events
+            .iter()
+            .find_map(|e| match &e.event {
+                Event::Transform(t) => Some(t),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(event.target, Some(standard.clone()));
+        assert_eq!(event.source, bundle_digest(&mmio));
+        assert_eq!(event.output.as_ref().unwrap().rows, 3);
+        assert_eq!(
+            transformed.log.events[mmio.log.events.len() - 1].digest,
+            mmio.log.head().cloned()
+        );
+        let prov = transformed.export_provenance(ProvFormat::Json).unwrap();
+        assert!(prov.contains("m2io:Transform"));
+        assert!(prov.contains(&format!("said:{}", event.output.as_ref().unwrap().said)));
+
+        let renamed = mmio.transform(&standard2).unwrap();
+        assert_eq!(
+            renamed.data.unwrap().records.get_column_names_str(),
+            ["first_name", "hgt"]
+        );
+        let unlinked = compile_ocafile("ADD ATTRIBUTE full_name=Text\n").unwrap();
+        assert!(matches!(
+            mmio.transform(&unlinked),
+            Err(MmioError::InvalidSemantics(_))
+        ));
+
+        // Links may only produce attributes of the target bundle
+        let mut stray = infer_semantics(&records, None).unwrap();
+        stray.ingest(&records).unwrap();
+        stray
+            .link(&Link::new(
+                other.clone(),
+                linkage(&[("name", "first_name"), ("weight", "wgt")]),
+            ))
+            .unwrap();
+        assert!(matches!(
+            stray.transform(&standard2),
+            Err(MmioError::InvalidSemantics(m)) if m.contains("'wgt'")
+        ));
+        assert!(matches!(
+            mmio.link(&Link::new(other.clone(), linkage(&[("age", "years")]))),
+            Err(MmioError::InvalidSemantics(_))
+        ));
+        let mixed = Link::new(other.clone(), linkage(&[("name", "x"), ("height", "x")]));
+        assert!(matches!(
+            mixed.apply(&records),
+            Err(MmioError::InvalidSemantics(_))
+        ));
+        let summed = Link::new(other, linkage(&[("height", "x"), ("weight", "x")]));
+        assert!(matches!(
+            summed.apply(&records),
+            Err(MmioError::InvalidSemantics(_))
+        ));
+
+        // Bundles read from published JSON get their overlay definitions
+        let wire = r#"{"v":"OCAS20JSON000320_","digest":"EHJ58dssK7HxXJjATIdMjXy2aoJpZRH7cIai5NXPQaZD","capture_base":{"digest":"EHZSMO2EFsXy8r5XogQ381-VmOiTUQYjV3WNkBfWYaCH","type":"capture_base/2.0.0","attributes":{"first_name":"Text","hgt":"Numeric","last_name":"Text","wgt":"Numeric"}},"overlays":[{"digest":"EP9iNoIrLu9w3YAMNW8FLWj5sP6VpOIoTIvDeC_6kvK0","capture_base":"EHZSMO2EFsXy8r5XogQ381-VmOiTUQYjV3WNkBfWYaCH","type":"overlay/meta/2.0.0","description":"Standard 1 Patient BMI","language":"eng","name":"Patient BMI"}]}"#;
+        let mut published = MMIO::new();
+        published
+            .add_modality(Modality {
+                digest: None,
+                modality_said: None,
+                modality_type: ModalityType::Tabular,
+                media_type: ARROW_MEDIA_TYPE.to_string(),
+                oca_bundle: Semantic::Bundle(parse_oca_bundle(wire).unwrap()),
+                locator: None,
+            })
+            .unwrap();
+        published
+            .link(&Link::new(standard, linkage(&[("hgt", "height")])))
+            .unwrap();
+        let Semantic::Bundle(bundle) = &published.modalities[0].oca_bundle else {
+            panic!("linking keeps the bundle embedded");
+        };
+        assert_eq!(bundle.overlays.len(), 2);
+        assert!(bundle.digest.is_some());
+    }
+
+    #[test]
+    fn test_from_ocafile() {
+        use oca_sdk_rs::{AttributeType, NestedAttrType};
+
+        let fake = include_str!("../docs/examples/assets/fake.ocafile");
+        let Semantic::Bundle(bundle) = Semantic::from_ocafile(fake).unwrap() else {
+            panic!("OCAfiles compile into embedded bundles");
+        };
+        assert!(bundle.digest.is_some());
+        assert_eq!(
+            bundle.capture_base.attributes["height"],
+            NestedAttrType::Value(AttributeType::Numeric)
+        );
+        assert_eq!(bundle.overlays.len(), 2);
+        // The same source always compiles to the same bundle
+        let mock = include_str!("../docs/examples/assets/mock.ocafile");
+        assert_eq!(
+            compile_ocafile(mock).unwrap().digest,
+            compile_ocafile(mock).unwrap().digest
+        );
+
+        let errors = [
+            ("ADD ATTRIBUTE name=Text\nADD ATRIBUTE age=Numeric\n", 2),
+            (
+                "-- name=x\n\nADD ATTRIBUTE name=Text\nADD OVERLAY NONEXISTENT\n  foo=\"bar\"\n",
+                4,
+            ),
+            (
+                "ADD ATTRIBUTE name=Text\nADD OVERLAY META\n  name=\"x\"\nADD OVERLAY META\n  language=\"en\"\n",
+                2,
+            ),
+            (include_str!("../docs/examples/assets/fake2mock.ocafile"), 2),
+        ];
+        for (source, line) in errors {
+            match compile_ocafile(source) {
+                Err(MmioError::InvalidOcafile { line: l, message }) => {
+                    assert_eq!(l, line, "{}", message)
+                }
+                other => panic!(
+                    "expected an OCAfile error, got {:?}",
+                    other.map(|b| b.digest)
+                ),
+            }
+        }
+
+        let link =
+            Link::from_ocafile(include_str!("../docs/examples/assets/fake2mock.ocafile")).unwrap();
+        assert_eq!(
+            link.target.to_string(),
+            "EO-x8moBEOoLq-KXiukQYt0OTo6u9Rv8TYjNvMhm_bXf"
+        );
+        assert_eq!(link.attributes.len(), 4);
+        assert_eq!(link.attributes[0], ("name".into(), "first_name".into()));
+        assert!(matches!(
+            Link::from_ocafile(
+                "-- target=refs:EBA3iXoZRgnJzu9L1OwR0Ke8bcTQ4B8IeJYFatiXMfh7\n\nLINK name\n"
+            ),
+            Err(MmioError::InvalidOcafile { line: 3, .. })
+        ));
+    }
+
+    #[test]
+    fn test_example_assets() {
+        let fake = compile_ocafile(include_str!("../docs/examples/assets/fake.ocafile")).unwrap();
+        let mock = compile_ocafile(include_str!("../docs/examples/assets/mock.ocafile")).unwrap();
+        let source = include_str!("../docs/examples/assets/fake2mock.ocafile");
+        let meta = semantics::ocafile_meta(source);
+        let linked_from = meta
+            .iter()
+            .find(|(_, key, _)| key == "source")
+            .map(|(_, _, value)| value.strip_prefix("refs:").unwrap_or(value))
+            .unwrap();
+        assert_eq!(linked_from, fake.digest.unwrap().to_string());
+
+        let link = Link::from_ocafile(source).unwrap();
+        assert_eq!(Some(link.target), mock.digest);
+        let attributes: Vec<_> = mock.capture_base.attributes.keys().collect();
+        for (_, target) in &link.attributes {
+            assert!(attributes.contains(&target), "{}", target);
+        }
+    }
+
+    #[test]
+    fn test_binary_formats() {
+        let fake = compile_ocafile(include_str!("../docs/examples/assets/fake.ocafile")).unwrap();
+        let mut mmio = MMIO::builder()
+            .add_modality(
+                Modality::builder()
+                    .with_bytes(b"name,surname\nJan,Kowalski\n".to_vec(), "text/csv")
+                    .with_semantic_bundle(fake.clone())
+                    .with_locator(Locator::Path("visits.csv".into())),
+            )
+            .add_modality(
+                Modality::builder()
+                    .with_bytes(b"\x89PNG\r\n\x1a\nscan".to_vec(), "image/png")
+                    .with_semantic_reference(fake.digest.clone().unwrap())
+                    .with_hash(HashFunctionCode::SHA2_256),
+            )
+            .build()
+            .unwrap();
+        mmio.commit_provenance().unwrap();
+        let json = mmio.to_bytes(Format::Json).unwrap();
+
+        for format in [Format::Cbor, Format::MsgPack] {
+            let bytes = mmio.to_bytes(format).unwrap();
+            assert!(bytes.len() < json.len());
+            assert_eq!(Format::detect(&bytes), Some(format));
+            let decoded = MMIO::from_bytes(&bytes, None).unwrap();
+            assert_eq!(decoded.digest, mmio.digest);
+            assert!(decoded.verify().unwrap().is_valid());
+            assert_eq!(decoded.to_bytes(Format::Json).unwrap(), json);
+            assert_eq!(decoded.to_bytes(format).unwrap(), bytes);
+
+            let semantic = &mmio.modalities[0].oca_bundle;
+            let decoded: Semantic = format.decode(&format.encode(semantic).unwrap()).unwrap();
+            assert!(matches!(decoded, Semantic::Bundle(b) if b.digest == fake.digest));
+            assert!(matches!(
+                MMIO::from_bytes(&bytes, Some(Format::Json)),
+                Err(MmioError::Serialization(_))
+            ));
+        }
+        let mut trailing = mmio.to_bytes(Format::Cbor).unwrap();
+        trailing.push(0);
+        assert!(matches!(
+            MMIO::from_bytes(&trailing, None),
+            Err(MmioError::Encoding(_))
+        ));
+        assert_eq!(Format::detect(&json), Some(Format::Json));
+        assert_eq!("MsgPack".parse::<Format>().unwrap(), Format::MsgPack);
+        assert!(matches!(
+            "yaml".parse::<Format>(),
+            Err(MmioError::Encoding(_))
+        ));
+        assert!(matches!(
+            MMIO::from_bytes(b"\x00", None),
+            Err(MmioError::Encoding(_))
+        ));
+    }
+
+    #[test]
+    fn test_cesr_stream() {
+        let fake = compile_ocafile(include_str!("../docs/examples/assets/fake.ocafile")).unwrap();
+        let mut first = MMIO::builder()
+            .add_modality(
+                Modality::builder()
+                    .with_bytes(b"name,surname\nJan,Kowalski\n".to_vec(), "text/csv")
+                    .with_semantic_bundle(fake.clone()),
+            )
+            .build()
+            .unwrap();
+        first.commit_provenance().unwrap();
+        let second = MMIO::builder()
+            .add_modality(
+                Modality::builder()
+                    .with_bytes(b"\x89PNG\r\n\x1a\nscan".to_vec(), "image/png")
+                    .with_semantic_reference(fake.digest.clone().unwrap()),
+            )
+            .build()
+            .unwrap();
+
+        let mut stream = vec![];
+        for (mmio, format) in [
+            (&first, Format::Json),
+            (&second, Format::Cbor),
+            (&first, Format::MsgPack),
+        ] {
+            let message = mmio.to_cesr(format).unwrap();
+            let kind = match format {
+                Format::Json => "JSON",
+                Format::Cbor => "CBOR",
+                Format::MsgPack => "MGPK",
+            };
+            let version = format!("MMIO02{}{:06x}_", kind, message.len());
+            assert!(message[..32]
+                .windows(version.len())
+                .any(|w| w == version.as_bytes()));
+            assert_eq!(MMIO::from_cesr(&message).unwrap().digest, mmio.digest);
+            stream.extend(message);
+        }
+        assert!(stream.starts_with(b"{\"v\":\"MMIO02JSON"));
+
+        let parsed = parse_cesr_stream(&stream).unwrap();
+        let digests: Vec<_> = parsed.iter().map(|m| m.digest.clone()).collect();
+        assert_eq!(
+            digests,
+            vec![
+                first.digest.clone(),
+                second.digest.clone(),
+                first.digest.clone()
+            ]
+        );
+        for mmio in &parsed {
+            assert!(mmio.verify().unwrap().is_valid());
+        }
+        let read: Vec<_> = CesrReader::new(stream.as_slice())
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(read.len(), 3);
+        assert!(parse_cesr_stream(b"").unwrap().is_empty());
+
+        // A truncated stream fails, after the messages before the cut
+        let mut reader = CesrReader::new(&stream[..stream.len() - 1]);
+        assert!(reader.next().unwrap().is_ok());
+        assert!(reader.next().unwrap().is_ok());
+        assert!(matches!(reader.next(), Some(Err(MmioError::Encoding(_)))));
+        assert!(reader.next().is_none());
+        assert!(matches!(
+            parse_cesr_stream(&first.to_bytes(Format::Json).unwrap()),
+            Err(MmioError::Encoding(_))
+        ));
+        let message = first.to_cesr(Format::Json).unwrap();
+        assert!(matches!(
+            MMIO::from_cesr(&[message.as_slice(), b"{"].concat()),
+            Err(MmioError::Encoding(_))
+        ));
     }
 }
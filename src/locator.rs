@@ -0,0 +1,429 @@
+//! Where the payload of a modality can be found.
+//!
+//! A [`Locator`] is not part of the digest of a modality or of the MMIO
+//! holding it: the payload is already committed to by `modality_said`, so
+//! a payload can move or be inlined without changing any SAID. Whatever a
+//! locator points at is checked against `modality_said` when it is read.
+
+use std::fs::File;
+use std::io::{Cursor, Read, Write};
+use std::path::{Component, Path};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use said::derivation::HashFunctionCode;
+use said::SelfAddressingIdentifier;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::hash::StreamHasher;
+use crate::{hash_code_of, parse_said, MmioArchive, MmioError, Modality};
+
+/// Location of a modality payload.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "value")]
+pub enum Locator {
+    /// The payload itself, base64 encoded. Meant for small payloads.
+    Inline(String),
+    /// Path relative to the directory of the MMIO document.
+    Path(String),
+    /// Key of the payload in a [`ContentStore`].
+    Store(SelfAddressingIdentifier),
+    /// A `file://` URI, or an `http://` or `https://` URL when built with
+    /// the `http` feature.
+    Uri(String),
+}
+
+impl Locator {
+    /// Locator holding `payload` inline.
+    pub fn inline(payload: &[u8]) -> Self {
+        Locator::Inline(STANDARD.encode(payload))
+    }
+
+    /// Locator of the given type, `Inline`, `Path`, `Store` or `Uri`, as in
+    /// its JSON form. `Inline` values are base64 encoded.
+    pub fn from_parts(kind: &str, value: &str) -> Result<Self, MmioError> {
+        match kind {
+            "Inline" => {
+                STANDARD.decode(value).map_err(|e| {
+                    MmioError::InvalidModality(format!("invalid inline payload: {}", e))
+                })?;
+                Ok(Locator::Inline(value.to_string()))
+            }
+            "Path" => Ok(Locator::Path(value.to_string())),
+            "Store" => Ok(Locator::Store(parse_said(value)?)),
+            "Uri" => Ok(Locator::Uri(value.to_string())),
+            _ => Err(MmioError::InvalidModality(format!(
+                "unknown locator type '{}', expected Inline, Path, Store or Uri",
+                kind
+            ))),
+        }
+    }
+}
+
+/// Store of payloads keyed by SAID.
+pub trait ContentStore {
+    /// Open the payload stored under `key`, or `None` if it is not stored.
+    fn open<'a>(
+        &'a self,
+        key: &SelfAddressingIdentifier,
+    ) -> Result<Option<Box<dyn Read + 'a>>, MmioError>;
+}
+
+impl ContentStore for MmioArchive {
+    fn open<'a>(
+        &'a self,
+        key: &SelfAddressingIdentifier,
+    ) -> Result<Option<Box<dyn Read + 'a>>, MmioError> {
+        Ok(self
+            .payload(key)?
+            .map(|reader| Box::new(reader) as Box<dyn Read + 'a>))
+    }
+}
+
+/// What locators are resolved against.
+#[derive(Clone, Copy, Default)]
+pub struct PayloadContext<'a> {
+    /// Directory `Path` locators are relative to, the current directory
+    /// if not given.
+    pub base_dir: Option<&'a Path>,
+    /// Store for `Store` locators.
+    pub store: Option<&'a dyn ContentStore>,
+}
+
+/// Reader of a payload that hashes the bytes as they are read. Reaching the
+/// end of a payload that does not match its SAID fails with an
+/// [`std::io::ErrorKind::InvalidData`] error, so the content is only known
+/// to be genuine once the whole stream has been read.
+pub struct PayloadReader<'a> {
+    inner: Box<dyn Read + 'a>,
+    hasher: Option<StreamHasher>,
+    code: HashFunctionCode,
+    expected: SelfAddressingIdentifier,
+}
+
+impl<'a> PayloadReader<'a> {
+    pub fn new(inner: Box<dyn Read + 'a>, expected: SelfAddressingIdentifier) -> Self {
+        let code = hash_code_of(&expected);
+        Self {
+            inner,
+            hasher: Some(StreamHasher::new(&code)),
+            code,
+            expected,
+        }
+    }
+
+    /// Read the whole payload, checking it against its SAID.
+    pub fn read_verified(mut self) -> Result<Vec<u8>, MmioError> {
+        let mut payload = vec![];
+        self.read_to_end(&mut payload).map_err(verified_error)?;
+        Ok(payload)
+    }
+
+    /// Copy the whole payload to `writer`, checking it against its SAID.
+    /// Returns the number of bytes copied. On a mismatch the bytes are
+    /// written already.
+    pub fn copy_to<W: Write>(mut self, writer: &mut W) -> Result<u64, MmioError> {
+        std::io::copy(&mut self, writer).map_err(verified_error)
+    }
+}
+
+/// Turn the error a [`PayloadReader`] fails with on a SAID mismatch back
+/// into [`MmioError::SaidMismatch`].
+pub(crate) fn verified_error(e: std::io::Error) -> MmioError {
+    match e
+        .get_ref()
+        .and_then(|inner| inner.downcast_ref::<MmioError>())
+    {
+        Some(MmioError::SaidMismatch { expected, actual }) => MmioError::SaidMismatch {
+            expected: expected.clone(),
+            actual: actual.clone(),
+        },
+        _ => e.into(),
+    }
+}
+
+impl Read for PayloadReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        if n > 0 {
+            if let Some(hasher) = self.hasher.as_mut() {
+                hasher.update(&buf[..n]);
+            }
+        } else if !buf.is_empty() {
+            if let Some(hasher) = self.hasher.take() {
+                let actual = hasher.finalize(&self.code);
+                if actual != self.expected {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::InvalidData,
+                        MmioError::SaidMismatch {
+                            expected: self.expected.clone(),
+                            actual,
+                        },
+                    ));
+                }
+            }
+        }
+        Ok(n)
+    }
+}
+
+fn relative_path(path: &str) -> Result<&Path, MmioError> {
+    let path = Path::new(path);
+    if path
+        .components()
+        .all(|c| matches!(c, Component::Normal(_) | Component::CurDir))
+    {
+        Ok(path)
+    } else {
+        Err(MmioError::InvalidModality(format!(
+            "locator path '{}' must stay within the document directory",
+            path.display()
+        )))
+    }
+}
+
+fn invalid_uri(uri: &str, reason: &str) -> MmioError {
+    MmioError::InvalidModality(format!("locator URI '{}' {}", uri, reason))
+}
+
+fn open_uri(uri: &str) -> Result<Box<dyn Read>, MmioError> {
+    let url = Url::parse(uri).map_err(|e| invalid_uri(uri, &e.to_string()))?;
+    match url.scheme() {
+        "file" => {
+            // Only local files: the host must be empty or `localhost`
+            let path = url
+                .to_file_path()
+                .map_err(|_| invalid_uri(uri, "is not a local file path"))?;
+            Ok(Box::new(File::open(path)?))
+        }
+        #[cfg(feature = "http")]
+        "http" | "https" => {
+            let response = ureq::get(url.as_str())
+                .call()
+                .map_err(|e| std::io::Error::other(format!("{}: {}", uri, e)))?;
+            Ok(Box::new(response.into_body().into_reader()))
+        }
+        #[cfg(not(feature = "http"))]
+        "http" | "https" => Err(invalid_uri(
+            uri,
+            "needs m2io to be built with the `http` feature",
+        )),
+        _ => Err(invalid_uri(
+            uri,
+            "is not a file://, http:// or https:// URI",
+        )),
+    }
+}
+
+impl Modality {
+    /// Open the payload this modality's locator points at. The bytes are
+    /// checked against `modality_said` as they are read, see
+    /// [`PayloadReader`].
+    pub fn open_payload<'a>(
+        &self,
+        context: &PayloadContext<'a>,
+    ) -> Result<PayloadReader<'a>, MmioError> {
+        let expected = self.modality_said.clone().ok_or_else(|| {
+            MmioError::InvalidModality("the modality has no modality_said".into())
+        })?;
+        let locator = self
+            .locator
+            .as_ref()
+            .ok_or_else(|| MmioError::InvalidModality("the modality has no locator".into()))?;
+        let inner: Box<dyn Read + 'a> = match locator {
+            Locator::Inline(encoded) => {
+                let payload = STANDARD.decode(encoded).map_err(|e| {
+                    MmioError::InvalidModality(format!("invalid inline payload: {}", e))
+                })?;
+                Box::new(Cursor::new(payload))
+            }
+            Locator::Path(path) => {
+                let path = relative_path(path)?;
+                let base = context.base_dir.unwrap_or(Path::new("."));
+                Box::new(File::open(base.join(path))?)
+            }
+            Locator::Store(key) => {
+                let store = context.store.ok_or_else(|| {
+                    MmioError::InvalidModality(format!("no content store to find {} in", key))
+                })?;
+                store.open(key)?.ok_or_else(|| {
+                    MmioError::InvalidModality(format!("payload {} is not in the store", key))
+                })?
+            }
+            Locator::Uri(uri) => open_uri(uri)?,
+        };
+        Ok(PayloadReader::new(inner, expected))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use said::derivation::HashFunction;
+
+    use super::*;
+    use crate::{Semantic, DEFAULT_HASH, MMIO};
+
+    #[test]
+    fn test_payload_locators() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        std::fs::create_dir_all(dir.join("scans")).unwrap();
+        let payload = b"\x89PNG\r\n\x1a\nscan".to_vec();
+        std::fs::write(dir.join("scans/scan.png"), &payload).unwrap();
+        let bundle_said = HashFunction::from(DEFAULT_HASH).derive(b"bundle");
+        let reference = Semantic::Reference(bundle_said.clone());
+        let modality =
+            Modality::from_reader(payload.as_slice(), reference.clone(), &Default::default())
+                .unwrap();
+        let read = |modality: &Modality, context: &PayloadContext| {
+            modality.open_payload(context)?.read_verified()
+        };
+        let with_locator = |locator| {
+            let mut mmio = MMIO::new();
+            mmio.add_modality(
+                Modality::builder()
+                    .with_bytes(payload.clone(), "image/png")
+                    .with_semantic_reference(bundle_said.clone())
+                    .with_locator(locator)
+                    .build()
+                    .unwrap(),
+            )
+            .unwrap();
+            mmio.compute_digest().unwrap();
+            mmio
+        };
+
+        // Locators do not change any digest
+        let mut mmio = with_locator(Locator::inline(&payload));
+        assert_eq!(mmio.modalities[0].digest, modality.digest);
+        let mut unlocated = mmio.clone();
+        unlocated.modalities[0].locator = None;
+        assert_eq!(
+            unlocated.compute_digest().unwrap(),
+            *mmio.digest.as_ref().unwrap()
+        );
+        assert!(mmio.verify().unwrap().is_valid());
+        let json = serde_json::to_string(&mmio).unwrap();
+        assert!(json.contains(r#""locator":{"type":"Inline""#));
+        assert!(!serde_json::to_string(&unlocated)
+            .unwrap()
+            .contains("locator"));
+        assert_eq!(
+            MMIO::from_json(&json).unwrap().modalities[0].locator,
+            mmio.modalities[0].locator
+        );
+
+        let in_dir = PayloadContext {
+            base_dir: Some(dir),
+            store: None,
+        };
+        assert_eq!(read(&mmio.modalities[0], &in_dir).unwrap(), payload);
+        mmio.modalities[0].locator = Some(Locator::Path("scans/scan.png".into()));
+        assert_eq!(read(&mmio.modalities[0], &in_dir).unwrap(), payload);
+        mmio.modalities[0].locator = Some(Locator::Path("../scan.png".into()));
+        assert!(matches!(
+            read(&mmio.modalities[0], &in_dir),
+            Err(MmioError::InvalidModality(_))
+        ));
+        std::fs::write(dir.join("scans/my scan.png"), &payload).unwrap();
+        let uri = url::Url::from_file_path(dir.join("scans/my scan.png")).unwrap();
+        assert!(uri.as_str().contains("my%20scan.png"));
+        let localhost = uri.as_str().replacen("file://", "file://localhost", 1);
+        for uri in [uri.to_string(), localhost] {
+            mmio.modalities[0].locator = Some(Locator::Uri(uri));
+            assert_eq!(read(&mmio.modalities[0], &in_dir).unwrap(), payload);
+        }
+        for uri in [
+            "ftp://example.org/scan.png",
+            "file://example.org/scan.png",
+            "scans/scan.png",
+        ] {
+            mmio.modalities[0].locator = Some(Locator::Uri(uri.into()));
+            assert!(matches!(
+                read(&mmio.modalities[0], &in_dir),
+                Err(MmioError::InvalidModality(_))
+            ));
+        }
+        assert_eq!(
+            Locator::from_parts("Path", "scans/scan.png").unwrap(),
+            Locator::Path("scans/scan.png".into())
+        );
+        assert!(Locator::from_parts("Inline", "not base64!").is_err());
+        assert!(Locator::from_parts("Url", "https://example.org").is_err());
+
+        // Payloads in a content store, here an archive
+        let said = modality.modality_said.clone().unwrap();
+        mmio.modalities[0].locator = Some(Locator::Store(said.clone()));
+        assert!(read(&mmio.modalities[0], &in_dir).is_err());
+        let mut archive = MmioArchive::new(unlocated.clone()).unwrap();
+        archive.add_payload(0, payload.clone()).unwrap();
+        let from_store = PayloadContext {
+            base_dir: None,
+            store: Some(&archive),
+        };
+        assert_eq!(read(&mmio.modalities[0], &from_store).unwrap(), payload);
+
+        // A modified payload fails once the stream is read to the end
+        std::fs::write(dir.join("scans/scan.png"), b"\x89PNG\r\n\x1a\nedit").unwrap();
+        mmio.modalities[0].locator = Some(Locator::Path("scans/scan.png".into()));
+        let mut reader = mmio.modalities[0].open_payload(&in_dir).unwrap();
+        let mut head = [0u8; 4];
+        reader.read_exact(&mut head).unwrap();
+        let error = std::io::copy(&mut reader, &mut std::io::sink()).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        assert!(matches!(
+            read(&mmio.modalities[0], &in_dir),
+            Err(MmioError::SaidMismatch { actual, .. }) if actual != said
+        ));
+    }
+
+    #[cfg(feature = "http")]
+    mod http {
+        use std::io::{BufRead, BufReader};
+        use std::net::TcpListener;
+
+        use super::*;
+
+        /// Serve `body` once over plain HTTP on a local port.
+        fn serve(body: &'static [u8]) -> String {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let address = listener.local_addr().unwrap();
+            std::thread::spawn(move || {
+                let (stream, _) = listener.accept().unwrap();
+                let mut request = BufReader::new(&stream);
+                let mut line = String::new();
+                while request.read_line(&mut line).unwrap() > 2 {
+                    line.clear();
+                }
+                let mut stream = &stream;
+                write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    body.len()
+                )
+                .unwrap();
+                stream.write_all(body).unwrap();
+            });
+            format!("http://{}/scan.png", address)
+        }
+
+        #[test]
+        fn test_http_locator() {
+            let payload = b"\x89PNG\r\n\x1a\nscan";
+            let semantic = Semantic::Reference(HashFunction::from(DEFAULT_HASH).derive(b"bundle"));
+            let mut modality =
+                Modality::from_reader(&payload[..], semantic, &Default::default()).unwrap();
+
+            modality.locator = Some(Locator::Uri(serve(payload)));
+            let context = PayloadContext::default();
+            let read = modality.open_payload(&context).unwrap().read_verified();
+            assert_eq!(read.unwrap(), payload);
+
+            modality.locator = Some(Locator::Uri(serve(b"\x89PNG\r\n\x1a\nedit")));
+            assert!(matches!(
+                modality.open_payload(&context).unwrap().read_verified(),
+                Err(MmioError::SaidMismatch { .. })
+            ));
+        }
+    }
+}
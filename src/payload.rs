@@ -0,0 +1,384 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use pyo3::prelude::*;
+use said::derivation::HashFunctionCode;
+use said::SelfAddressingIdentifier;
+
+use crate::{derive_from_reader, hash_code_of, MmioError, MMIO};
+
+/// Number of leading bytes inspected to infer the media type of a payload.
+const SNIFF_LEN: usize = 512;
+
+/// Hash a payload stream and infer its media type from its first bytes. The
+/// bytes read for sniffing are hashed together with the rest of the stream.
+pub fn hash_payload<R: Read>(
+    mut reader: R,
+    code: &HashFunctionCode,
+) -> Result<(SelfAddressingIdentifier, &'static str), MmioError> {
+    let mut header = Vec::with_capacity(SNIFF_LEN);
+    (&mut reader)
+        .take(SNIFF_LEN as u64)
+        .read_to_end(&mut header)?;
+    let said = derive_from_reader(code, header.as_slice().chain(reader))?;
+    let media_type =
+        infer::get(&header).map_or("application/octet-stream", |kind| kind.mime_type());
+    Ok((said, media_type))
+}
+
+/// SAID of a file as earlier versions of the CLI computed it: the first
+/// [`SNIFF_LEN`] bytes, read to infer the media type, were left out of the
+/// hash. Files no larger than that all hash like an empty file.
+fn hash_file_legacy(
+    path: &Path,
+    code: &HashFunctionCode,
+) -> Result<SelfAddressingIdentifier, MmioError> {
+    let mut file = File::open(path)?;
+    std::io::copy(
+        &mut (&mut file).take(SNIFF_LEN as u64),
+        &mut std::io::sink(),
+    )?;
+    Ok(derive_from_reader(code, file)?)
+}
+
+/// Compute the SAID of a file's content with the given hash algorithm.
+pub fn hash_file(
+    path: &Path,
+    code: &HashFunctionCode,
+) -> Result<SelfAddressingIdentifier, MmioError> {
+    let file = File::open(path)?;
+    Ok(derive_from_reader(code, file)?)
+}
+
+/// Outcome of checking one modality payload against its `modality_said`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PayloadStatus {
+    /// The payload was found and matches `modality_said`.
+    Verified,
+    /// No payload was found for the modality.
+    Missing,
+    /// The payload was found but its content hashes to a different SAID.
+    Modified { actual: SelfAddressingIdentifier },
+    /// The modality has no `modality_said`, so there is nothing to check.
+    NoSaid,
+    /// The payload matches `modality_said` only without its first 512
+    /// bytes, as earlier versions of the CLI hashed it. The modality should
+    /// be created again to commit to the whole payload.
+    LegacySaid,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PayloadCheck {
+    /// Position in `MMIO.modalities`.
+    pub index: usize,
+    /// File that was checked, if one was found.
+    pub path: Option<PathBuf>,
+    pub expected: Option<SelfAddressingIdentifier>,
+    pub status: PayloadStatus,
+}
+
+impl std::fmt::Display for PayloadCheck {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let path = self
+            .path
+            .as_ref()
+            .map_or_else(|| "-".to_string(), |p| p.display().to_string());
+        match &self.status {
+            PayloadStatus::Verified => write!(f, "modality {}: ok ({})", self.index, path),
+            PayloadStatus::Missing => write!(
+                f,
+                "modality {}: missing payload {}",
+                self.index,
+                self.expected
+                    .as_ref()
+                    .map_or_else(String::new, |s| s.to_string())
+            ),
+            PayloadStatus::Modified { actual } => write!(
+                f,
+                "modality {}: modified ({}), expected {}, computed {}",
+                self.index,
+                path,
+                self.expected
+                    .as_ref()
+                    .map_or_else(String::new, |s| s.to_string()),
+                actual
+            ),
+            PayloadStatus::NoSaid => write!(f, "modality {}: no modality_said", self.index),
+            PayloadStatus::LegacySaid => write!(
+                f,
+                "modality {}: legacy modality_said ({}), the first 512 bytes are not covered",
+                self.index, path
+            ),
+        }
+    }
+}
+
+/// Result of checking modality payloads on disk.
+#[pyclass]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PayloadReport {
+    /// One entry per modality, in document order.
+    pub payloads: Vec<PayloadCheck>,
+    /// Files found in the scanned directory that no modality refers to.
+    pub unexpected: Vec<PathBuf>,
+}
+
+impl PayloadReport {
+    /// Whether every payload is verified and no file is unexpected.
+    /// Modalities without a `modality_said` have no payload to check and do
+    /// not make the report fail.
+    pub fn is_valid(&self) -> bool {
+        self.unexpected.is_empty()
+            && self
+                .payloads
+                .iter()
+                .all(|p| matches!(p.status, PayloadStatus::Verified | PayloadStatus::NoSaid))
+    }
+
+    /// Like [`PayloadReport::is_valid`], but also accepting payloads that
+    /// match a legacy `modality_said`.
+    pub fn is_valid_allowing_legacy(&self) -> bool {
+        self.unexpected.is_empty()
+            && self.payloads.iter().all(|p| {
+                matches!(
+                    p.status,
+                    PayloadStatus::Verified | PayloadStatus::NoSaid | PayloadStatus::LegacySaid
+                )
+            })
+    }
+
+    fn indices(&self, f: impl Fn(&PayloadStatus) -> bool) -> Vec<usize> {
+        self.payloads
+            .iter()
+            .filter(|p| f(&p.status))
+            .map(|p| p.index)
+            .collect()
+    }
+}
+
+#[pymethods]
+impl PayloadReport {
+    #[getter(is_valid)]
+    fn py_is_valid(&self) -> bool {
+        self.is_valid()
+    }
+
+    #[getter]
+    fn get_missing(&self) -> Vec<usize> {
+        self.indices(|s| *s == PayloadStatus::Missing)
+    }
+
+    #[getter]
+    fn get_modified(&self) -> Vec<usize> {
+        self.indices(|s| matches!(s, PayloadStatus::Modified { .. }))
+    }
+
+    #[getter]
+    fn get_legacy(&self) -> Vec<usize> {
+        self.indices(|s| *s == PayloadStatus::LegacySaid)
+    }
+
+    #[getter]
+    fn get_unexpected(&self) -> Vec<PathBuf> {
+        self.unexpected.clone()
+    }
+
+    fn __bool__(&self) -> bool {
+        self.is_valid()
+    }
+}
+
+fn check(
+    index: usize,
+    expected: &Option<SelfAddressingIdentifier>,
+    path: Option<&Path>,
+    actual: Option<&SelfAddressingIdentifier>,
+    legacy: bool,
+) -> PayloadCheck {
+    let status = match (expected, actual) {
+        (None, _) => PayloadStatus::NoSaid,
+        (Some(_), None) => PayloadStatus::Missing,
+        (Some(expected), Some(actual)) if expected == actual => PayloadStatus::Verified,
+        (Some(_), Some(_)) if legacy => PayloadStatus::LegacySaid,
+        (Some(_), Some(actual)) => PayloadStatus::Modified {
+            actual: actual.clone(),
+        },
+    };
+    PayloadCheck {
+        index,
+        path: path.map(Path::to_path_buf),
+        expected: expected.clone(),
+        status,
+    }
+}
+
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) -> Result<(), MmioError> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_files(&path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+impl MMIO {
+    /// Check each modality payload against the file given for its index in
+    /// `paths`. Modalities without an entry or whose file does not exist are
+    /// reported as missing, and payloads matching only a legacy SAID as
+    /// [`PayloadStatus::LegacySaid`]. Paths that exist but are not files,
+    /// such as directories, are rejected.
+    pub fn verify_payloads(
+        &self,
+        paths: &BTreeMap<usize, PathBuf>,
+    ) -> Result<PayloadReport, MmioError> {
+        if let Some(index) = paths.keys().find(|i| **i >= self.modalities.len()) {
+            return Err(MmioError::InvalidModality(format!(
+                "no modality at index {}",
+                index
+            )));
+        }
+        if let Some((index, path)) = paths.iter().find(|(_, p)| p.exists() && !p.is_file()) {
+            return Err(MmioError::InvalidModality(format!(
+                "payload path '{}' of modality {} is not a file",
+                path.display(),
+                index
+            )));
+        }
+        let mut payloads = vec![];
+        for (i, modality) in self.modalities.iter().enumerate() {
+            let path = paths.get(&i).filter(|p| p.is_file());
+            let (actual, legacy) = match (&modality.modality_said, path) {
+                (Some(said), Some(p)) => {
+                    let code = hash_code_of(said);
+                    let actual = hash_file(p, &code)?;
+                    let legacy = actual != *said && hash_file_legacy(p, &code)? == *said;
+                    (Some(actual), legacy)
+                }
+                _ => (None, false),
+            };
+            payloads.push(check(
+                i,
+                &modality.modality_said,
+                paths.get(&i).map(PathBuf::as_path),
+                actual.as_ref(),
+                legacy,
+            ));
+        }
+        Ok(PayloadReport {
+            payloads,
+            unexpected: vec![],
+        })
+    }
+
+    /// Look for modality payloads anywhere under `dir`.
+    ///
+    /// A file named after a modality's `modality_said` is taken as that
+    /// modality's payload and reported as modified if its content does not
+    /// match. Otherwise any file whose content matches `modality_said` is
+    /// accepted. Files not claimed by any modality are reported as unexpected.
+    ///
+    /// Payloads are also checked against the legacy SAID of earlier CLI
+    /// versions and reported as [`PayloadStatus::LegacySaid`] on a match.
+    pub fn verify_payloads_in_dir(&self, dir: &Path) -> Result<PayloadReport, MmioError> {
+        let mut files = vec![];
+        collect_files(dir, &mut files)?;
+
+        // Every file is hashed once with each algorithm used by the modalities
+        let codes: HashSet<HashFunctionCode> = self
+            .modalities
+            .iter()
+            .filter_map(|m| m.modality_said.as_ref().map(hash_code_of))
+            .collect();
+        let hashes = files
+            .iter()
+            .map(|p| {
+                codes
+                    .iter()
+                    .map(|code| Ok((code.clone(), hash_file(p, code)?)))
+                    .collect::<Result<HashMap<_, _>, MmioError>>()
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut by_name: HashMap<&str, usize> = HashMap::new();
+        let mut by_content: HashMap<&SelfAddressingIdentifier, usize> = HashMap::new();
+        for (n, (path, saids)) in files.iter().zip(&hashes).enumerate() {
+            if let Some(stem) = path.file_stem().and_then(|s| s.to_str()) {
+                by_name.entry(stem).or_insert(n);
+            }
+            for said in saids.values() {
+                by_content.entry(said).or_insert(n);
+            }
+        }
+
+        // Legacy SAIDs are only computed for payloads that do not match
+        let mut legacy_hashes: HashMap<(usize, HashFunctionCode), SelfAddressingIdentifier> =
+            HashMap::new();
+        let mut legacy_hash =
+            |n: usize, code: &HashFunctionCode| -> Result<SelfAddressingIdentifier, MmioError> {
+                if let Some(said) = legacy_hashes.get(&(n, code.clone())) {
+                    return Ok(said.clone());
+                }
+                let said = hash_file_legacy(&files[n], code)?;
+                legacy_hashes.insert((n, code.clone()), said.clone());
+                Ok(said)
+            };
+
+        let mut claimed = HashSet::new();
+        let mut payloads = vec![];
+        for (i, modality) in self.modalities.iter().enumerate() {
+            let Some(said) = &modality.modality_said else {
+                payloads.push(check(i, &None, None, None, false));
+                continue;
+            };
+            let code = hash_code_of(said);
+            let mut found = by_name
+                .get(said.to_string().as_str())
+                .or_else(|| by_content.get(said))
+                .copied();
+            let mut legacy = false;
+            match found {
+                Some(n) if hashes[n][&code] != *said => legacy = legacy_hash(n, &code)? == *said,
+                Some(_) => {}
+                None => {
+                    // Small files all share the legacy SAID of empty content,
+                    // so only larger ones can be matched this way
+                    for (n, path) in files.iter().enumerate() {
+                        if claimed.contains(&n) || fs::metadata(path)?.len() <= SNIFF_LEN as u64 {
+                            continue;
+                        }
+                        if legacy_hash(n, &code)? == *said {
+                            found = Some(n);
+                            legacy = true;
+                            break;
+                        }
+                    }
+                }
+            }
+            if let Some(n) = found {
+                claimed.insert(n);
+            }
+            payloads.push(check(
+                i,
+                &modality.modality_said,
+                found.map(|n| files[n].as_path()),
+                found.map(|n| &hashes[n][&code]),
+                legacy,
+            ));
+        }
+
+        let unexpected = files
+            .iter()
+            .enumerate()
+            .filter(|(n, _)| !claimed.contains(n))
+            .map(|(_, p)| p.clone())
+            .collect();
+        Ok(PayloadReport {
+            payloads,
+            unexpected,
+        })
+    }
+}
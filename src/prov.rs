@@ -0,0 +1,452 @@
+//! Export of the provenance log as W3C PROV, in PROV-JSON or as PROV-O
+//! Turtle.
+//!
+//! The MMIO, its modalities, OCA bundles and records are entities
+//! identified by their SAIDs. Each `LoadBundle`, `Feed` and `Transform`
+//! event becomes an activity identified by the SAID of its log entry, and
+//! the user and m2io version recorded with it become agents.
+//!
+//! The MMIO and modality entities are their final states, which no single
+//! event generated, so events that changed them only influenced them. An
+//! entity is generated by one activity at most, as PROV requires.
+
+use std::fmt::Write as _;
+use std::time::SystemTime;
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use said::SelfAddressingIdentifier;
+use serde_json::{json, Map, Value};
+
+use crate::{DataReference, Event, LogEntry, MmioError, Sys, MMIO};
+
+const PREFIXES: &[(&str, &str)] = &[
+    ("prov", "http://www.w3.org/ns/prov#"),
+    ("xsd", "http://www.w3.org/2001/XMLSchema#"),
+    ("said", "urn:said:"),
+    ("m2io", "https://github.com/THCLab/mmio-rs/ns#"),
+];
+
+/// Output format of [`MMIO::export_provenance`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProvFormat {
+    /// W3C PROV-JSON.
+    Json,
+    /// PROV-O in RDF Turtle.
+    Turtle,
+}
+
+impl std::str::FromStr for ProvFormat {
+    type Err = MmioError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "prov-json" | "json" => Ok(ProvFormat::Json),
+            "ttl" | "turtle" => Ok(ProvFormat::Turtle),
+            _ => Err(MmioError::InvalidProvenance(format!(
+                "unknown export format '{}', expected prov-json or ttl",
+                s
+            ))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Kind {
+    Entity,
+    Activity,
+    Agent,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Literal {
+    String(String),
+    Integer(usize),
+    DateTime(SystemTime),
+}
+
+#[derive(Debug)]
+struct Node {
+    id: String,
+    kind: Kind,
+    types: Vec<&'static str>,
+    attributes: Vec<(&'static str, Literal)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Relation {
+    Used,
+    WasGeneratedBy,
+    WasInfluencedBy,
+    WasAssociatedWith,
+    ActedOnBehalfOf,
+    HadMember,
+}
+
+impl Relation {
+    /// PROV-JSON relation name, which is also the PROV-O property.
+    fn name(self) -> &'static str {
+        match self {
+            Relation::Used => "used",
+            Relation::WasGeneratedBy => "wasGeneratedBy",
+            Relation::WasInfluencedBy => "wasInfluencedBy",
+            Relation::WasAssociatedWith => "wasAssociatedWith",
+            Relation::ActedOnBehalfOf => "actedOnBehalfOf",
+            Relation::HadMember => "hadMember",
+        }
+    }
+
+    /// PROV-JSON keys of the subject and the object.
+    fn roles(self) -> (&'static str, &'static str) {
+        match self {
+            Relation::Used => ("prov:activity", "prov:entity"),
+            Relation::WasGeneratedBy => ("prov:entity", "prov:activity"),
+            Relation::WasInfluencedBy => ("prov:influencee", "prov:influencer"),
+            Relation::WasAssociatedWith => ("prov:activity", "prov:agent"),
+            Relation::ActedOnBehalfOf => ("prov:delegate", "prov:responsible"),
+            Relation::HadMember => ("prov:collection", "prov:entity"),
+        }
+    }
+
+    fn id_prefix(self) -> &'static str {
+        match self {
+            Relation::Used => "u",
+            Relation::WasGeneratedBy => "g",
+            Relation::WasInfluencedBy => "i",
+            Relation::WasAssociatedWith => "a",
+            Relation::ActedOnBehalfOf => "d",
+            Relation::HadMember => "m",
+        }
+    }
+}
+
+/// PROV document built from an MMIO and its log, before serialization.
+#[derive(Debug, Default)]
+struct ProvDocument {
+    nodes: Vec<Node>,
+    relations: Vec<(String, Relation, String)>,
+}
+
+impl ProvDocument {
+    fn from_mmio(mmio: &MMIO) -> Result<Self, MmioError> {
+        let mut doc = ProvDocument::default();
+        let mmio_id = said_id(mmio.digest.as_ref(), "the MMIO")?;
+        doc.add_node(
+            &mmio_id,
+            Kind::Entity,
+            &["prov:Collection", "m2io:MMIO"],
+            vec![("m2io:version", Literal::String(mmio.version.clone()))],
+        );
+        let mut modality_ids = vec![];
+        for (i, modality) in mmio.modalities.iter().enumerate() {
+            let id = said_id(modality.digest.as_ref(), &format!("modality {}", i))?;
+            doc.add_node(
+                &id,
+                Kind::Entity,
+                &["m2io:Modality"],
+                vec![
+                    (
+                        "m2io:mediaType",
+                        Literal::String(modality.media_type.clone()),
+                    ),
+                    (
+                        "m2io:modalityType",
+                        Literal::String(modality.modality_type.to_string()),
+                    ),
+                ],
+            );
+            doc.relate(&mmio_id, Relation::HadMember, &id);
+            modality_ids.push(id);
+        }
+
+        for (i, entry) in mmio.log.events.iter().enumerate() {
+            doc.add_entry(i, entry, &mmio_id, &modality_ids)?;
+        }
+        Ok(doc)
+    }
+
+    fn add_entry(
+        &mut self,
+        i: usize,
+        entry: &LogEntry,
+        mmio_id: &str,
+        modality_ids: &[String],
+    ) -> Result<(), MmioError> {
+        let (activity_type, time, sys) = match &entry.event {
+            Event::LoadBundle(e) => ("m2io:LoadBundle", e.time, &e.sys),
+            Event::Feed(e) => ("m2io:Feed", e.time, &e.sys),
+            Event::Transform(e) => ("m2io:Transform", e.time, &e.sys),
+            Event::ModalityAdded(_) | Event::DigestComputed(_) => return Ok(()),
+        };
+        let activity = said_id(entry.digest.as_ref(), &format!("event {}", i))?;
+        self.add_node(
+            &activity,
+            Kind::Activity,
+            &[activity_type],
+            // Events record when they happened, not how long they took
+            vec![("prov:startTime", Literal::DateTime(time))],
+        );
+        for agent in self.add_agents(sys) {
+            self.relate(&activity, Relation::WasAssociatedWith, &agent);
+        }
+
+        match &entry.event {
+            Event::LoadBundle(e) => {
+                if let Some(bundle) = &e.bundle {
+                    let bundle = format!("said:{}", bundle);
+                    self.add_node(&bundle, Kind::Entity, &["m2io:OCABundle"], vec![]);
+                    self.relate(&activity, Relation::Used, &bundle);
+                }
+                // The modality may have been removed since
+                if let Some(modality) = modality_ids.get(e.index) {
+                    self.relate(modality, Relation::WasInfluencedBy, &activity);
+                }
+            }
+            Event::Feed(e) => {
+                let data = self.add_records(&e.data);
+                self.relate(&activity, Relation::Used, &data);
+                self.relate(mmio_id, Relation::WasInfluencedBy, &activity);
+            }
+            Event::Transform(e) => {
+                for bundle in [&e.source, &e.target].into_iter().flatten() {
+                    let bundle = format!("said:{}", bundle);
+                    self.add_node(&bundle, Kind::Entity, &["m2io:OCABundle"], vec![]);
+                    self.relate(&activity, Relation::Used, &bundle);
+                }
+                if let Some(input) = &e.input {
+                    let input = self.add_records(input);
+                    self.relate(&activity, Relation::Used, &input);
+                }
+                if let Some(output) = &e.output {
+                    let output = self.add_records(output);
+                    self.generate(&output, &activity);
+                }
+                self.relate(mmio_id, Relation::WasInfluencedBy, &activity);
+            }
+            Event::ModalityAdded(_) | Event::DigestComputed(_) => unreachable!("skipped above"),
+        }
+        Ok(())
+    }
+
+    fn add_records(&mut self, data: &DataReference) -> String {
+        let id = format!("said:{}", data.said);
+        self.add_node(
+            &id,
+            Kind::Entity,
+            &["m2io:Records"],
+            vec![
+                ("m2io:rows", Literal::Integer(data.rows)),
+                ("m2io:schema", Literal::String(data.schema.to_string())),
+            ],
+        );
+        id
+    }
+
+    /// Add the software and, unless redacted, the user recorded in `sys`.
+    fn add_agents(&mut self, sys: &Sys) -> Vec<String> {
+        let software = format!("m2io:software-{}", escape_local(&sys.m2io_version));
+        let mut attributes = vec![];
+        if !sys.m2io_version.is_empty() {
+            attributes.push(("m2io:version", Literal::String(sys.m2io_version.clone())));
+        }
+        self.add_node(&software, Kind::Agent, &["prov:SoftwareAgent"], attributes);
+        let mut agents = vec![software.clone()];
+        if let Some(user) = &sys.user {
+            let person = format!("m2io:user-{}", escape_local(user));
+            let mut attributes = vec![("m2io:user", Literal::String(user.clone()))];
+            if let Some(hostname) = &sys.hostname {
+                attributes.push(("m2io:hostname", Literal::String(hostname.clone())));
+            }
+            self.add_node(&person, Kind::Agent, &["prov:Person"], attributes);
+            self.relate(&software, Relation::ActedOnBehalfOf, &person);
+            agents.push(person);
+        }
+        agents
+    }
+
+    /// Add a node unless one with the same id exists already.
+    fn add_node(
+        &mut self,
+        id: &str,
+        kind: Kind,
+        types: &[&'static str],
+        attributes: Vec<(&'static str, Literal)>,
+    ) {
+        if self.nodes.iter().any(|n| n.id == id) {
+            return;
+        }
+        self.nodes.push(Node {
+            id: id.to_string(),
+            kind,
+            types: types.to_vec(),
+            attributes,
+        });
+    }
+
+    /// Record that `activity` generated `entity`, unless an earlier one did.
+    /// The same records may come out of several transforms, but only the
+    /// first generated them; later ones just influenced them.
+    fn generate(&mut self, entity: &str, activity: &str) {
+        let generated = self
+            .relations
+            .iter()
+            .any(|(s, r, _)| s == entity && *r == Relation::WasGeneratedBy);
+        let relation = if generated {
+            Relation::WasInfluencedBy
+        } else {
+            Relation::WasGeneratedBy
+        };
+        self.relate(entity, relation, activity);
+    }
+
+    fn relate(&mut self, subject: &str, relation: Relation, object: &str) {
+        let triple = (subject.to_string(), relation, object.to_string());
+        if !self.relations.contains(&triple) {
+            self.relations.push(triple);
+        }
+    }
+
+    fn to_prov_json(&self) -> Value {
+        let mut doc = Map::new();
+        let prefix: Map<_, _> = PREFIXES
+            .iter()
+            .map(|(p, iri)| (p.to_string(), json!(iri)))
+            .collect();
+        doc.insert("prefix".into(), Value::Object(prefix));
+
+        for node in &self.nodes {
+            let mut attributes = Map::new();
+            let types: Vec<_> = node
+                .types
+                .iter()
+                .map(|t| json!({"$": t, "type": "prov:QUALIFIED_NAME"}))
+                .collect();
+            attributes.insert("prov:type".into(), Value::Array(types));
+            for (key, literal) in &node.attributes {
+                let value = match literal {
+                    Literal::String(s) => json!(s),
+                    Literal::Integer(n) => json!(n),
+                    Literal::DateTime(t) => json!(format_time(*t)),
+                };
+                attributes.insert(key.to_string(), value);
+            }
+            let section = match node.kind {
+                Kind::Entity => "entity",
+                Kind::Activity => "activity",
+                Kind::Agent => "agent",
+            };
+            doc.entry(section)
+                .or_insert_with(|| Value::Object(Map::new()))
+                .as_object_mut()
+                .expect("sections are objects")
+                .insert(node.id.clone(), Value::Object(attributes));
+        }
+
+        for (n, (subject, relation, object)) in self.relations.iter().enumerate() {
+            let (subject_role, object_role) = relation.roles();
+            doc.entry(relation.name())
+                .or_insert_with(|| Value::Object(Map::new()))
+                .as_object_mut()
+                .expect("sections are objects")
+                .insert(
+                    format!("_:{}{}", relation.id_prefix(), n + 1),
+                    json!({ subject_role: subject, object_role: object }),
+                );
+        }
+        Value::Object(doc)
+    }
+
+    fn to_turtle(&self) -> String {
+        let mut out = String::new();
+        for (prefix, iri) in PREFIXES {
+            let _ = writeln!(out, "@prefix {}: <{}> .", prefix, iri);
+        }
+        for node in &self.nodes {
+            let class = match node.kind {
+                Kind::Entity => "prov:Entity",
+                Kind::Activity => "prov:Activity",
+                Kind::Agent => "prov:Agent",
+            };
+            let mut statements = vec![format!(
+                "a {}",
+                std::iter::once(class)
+                    .chain(node.types.iter().copied())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )];
+            for (key, literal) in &node.attributes {
+                let key = match *key {
+                    "prov:startTime" => "prov:startedAtTime",
+                    key => key,
+                };
+                statements.push(format!("{} {}", key, turtle_literal(literal)));
+            }
+            for (subject, relation, object) in &self.relations {
+                if *subject == node.id {
+                    statements.push(format!("prov:{} {}", relation.name(), object));
+                }
+            }
+            let _ = write!(out, "\n{} {} .\n", node.id, statements.join(" ;\n    "));
+        }
+        out
+    }
+}
+
+impl MMIO {
+    /// Export the provenance log in the given format. The MMIO and its
+    /// modalities must have their digests computed, as those identify them.
+    pub fn export_provenance(&self, format: ProvFormat) -> Result<String, MmioError> {
+        let doc = ProvDocument::from_mmio(self)?;
+        Ok(match format {
+            ProvFormat::Json => serde_json::to_string_pretty(&doc.to_prov_json())?,
+            ProvFormat::Turtle => doc.to_turtle(),
+        })
+    }
+}
+
+fn said_id(said: Option<&SelfAddressingIdentifier>, what: &str) -> Result<String, MmioError> {
+    said.map(|said| format!("said:{}", said)).ok_or_else(|| {
+        MmioError::InvalidProvenance(format!("{} has no digest to identify it", what))
+    })
+}
+
+/// Percent-encode everything but ASCII alphanumerics, `-` and `_`, so the
+/// result is a valid local name in both PROV-JSON and Turtle.
+fn escape_local(s: &str) -> String {
+    let mut out = String::new();
+    for b in s.bytes() {
+        if b.is_ascii_alphanumeric() || b == b'-' || b == b'_' {
+            out.push(b as char);
+        } else {
+            let _ = write!(out, "%{:02X}", b);
+        }
+    }
+    if out.is_empty() {
+        out.push_str("unknown");
+    }
+    out
+}
+
+fn format_time(time: SystemTime) -> String {
+    DateTime::<Utc>::from(time).to_rfc3339_opts(SecondsFormat::Micros, true)
+}
+
+fn turtle_literal(literal: &Literal) -> String {
+    match literal {
+        Literal::String(s) => {
+            let mut out = String::from("\"");
+            for c in s.chars() {
+                match c {
+                    '"' => out.push_str("\\\""),
+                    '\\' => out.push_str("\\\\"),
+                    '\n' => out.push_str("\\n"),
+                    '\r' => out.push_str("\\r"),
+                    c => out.push(c),
+                }
+            }
+            out.push('"');
+            out
+        }
+        Literal::Integer(n) => format!("\"{}\"^^xsd:integer", n),
+        Literal::DateTime(t) => format!("\"{}\"^^xsd:dateTime", format_time(*t)),
+    }
+}
@@ -0,0 +1,331 @@
+//! Resolution of the bundles modalities reference by SAID.
+//!
+//! Bundles can also be named by an alias of the form `name@version`, such
+//! as `Standard1@1.0`, which a [`BundleStore`] maps to a SAID.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use oca_sdk_rs::{OCABundle, OCABundleModel};
+use said::SelfAddressingIdentifier;
+
+use crate::semantics::fill_overlay_defs;
+use crate::{parse_oca_bundle, parse_said, MmioError, Semantic, MMIO};
+
+/// Directory of the bundle store used when none is given.
+pub const DEFAULT_BUNDLE_STORE: &str = ".m2io/bundles";
+
+/// File of a [`BundleStore`] mapping aliases to SAIDs.
+const ALIASES_FILE: &str = "aliases.json";
+
+/// Source of OCA bundles by SAID.
+pub trait BundleResolver {
+    /// The bundle with digest `said`, or `None` if it is not known here.
+    /// A bundle that does not match `said` is an error.
+    fn resolve(&self, said: &SelfAddressingIdentifier)
+        -> Result<Option<OCABundleModel>, MmioError>;
+
+    /// The SAID of the bundle known as `alias`, or `None` if the alias is
+    /// not known here.
+    fn resolve_alias(&self, _alias: &str) -> Result<Option<SelfAddressingIdentifier>, MmioError> {
+        Ok(None)
+    }
+}
+
+/// Check that `alias` has the form `name@version`.
+fn check_alias(alias: &str) -> Result<(), MmioError> {
+    match alias.split_once('@') {
+        Some((name, version)) if !name.is_empty() && !version.is_empty() => Ok(()),
+        _ => Err(MmioError::InvalidSemantics(format!(
+            "bundle alias '{}' is not of the form name@version",
+            alias
+        ))),
+    }
+}
+
+/// Parse `target` as a bundle SAID or, if it has the form `name@version`,
+/// look it up as an alias with `resolver`.
+pub fn resolve_bundle_id(
+    target: &str,
+    resolver: &dyn BundleResolver,
+) -> Result<SelfAddressingIdentifier, MmioError> {
+    if !target.contains('@') {
+        return parse_said(target);
+    }
+    check_alias(target)?;
+    resolver.resolve_alias(target)?.ok_or_else(|| {
+        MmioError::InvalidSemantics(format!(
+            "unknown bundle alias '{}', add the bundle to the store with that alias first",
+            target
+        ))
+    })
+}
+
+/// Recompute the digest of `bundle` and check it against the one it holds.
+pub fn verify_bundle(bundle: &OCABundleModel) -> Result<SelfAddressingIdentifier, MmioError> {
+    let expected = bundle
+        .digest
+        .clone()
+        .ok_or_else(|| MmioError::InvalidSemantics("the bundle has no digest".into()))?;
+    let mut recomputed = bundle.clone();
+    fill_overlay_defs(&mut recomputed)?;
+    let actual = recomputed
+        .compute_and_fill_digest()
+        .map_err(|e| MmioError::InvalidSemantics(e.to_string()))?;
+    if actual != expected {
+        return Err(MmioError::SaidMismatch { expected, actual });
+    }
+    Ok(actual)
+}
+
+/// Content-addressed directory of bundles, each stored in its published
+/// JSON form as `<SAID>.json`. Bundles are verified when added and again
+/// when loaded.
+#[derive(Debug, Clone)]
+pub struct BundleStore {
+    root: PathBuf,
+}
+
+impl BundleStore {
+    /// Open the store at `root`, creating the directory if needed.
+    pub fn open(root: impl Into<PathBuf>) -> Result<Self, MmioError> {
+        let root = root.into();
+        fs::create_dir_all(&root)?;
+        Ok(Self { root })
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Path the bundle `said` is stored at.
+    pub fn path(&self, said: &SelfAddressingIdentifier) -> PathBuf {
+        self.root.join(format!("{}.json", said))
+    }
+
+    /// Verify `bundle` and store it under its SAID.
+    pub fn add(&self, bundle: &OCABundleModel) -> Result<SelfAddressingIdentifier, MmioError> {
+        let said = verify_bundle(bundle)?;
+        let mut published = bundle.clone();
+        fill_overlay_defs(&mut published)?;
+        let json = serde_json::to_string(&OCABundle::from(published))?;
+        fs::write(self.path(&said), json)?;
+        Ok(said)
+    }
+
+    /// Parse a bundle in either JSON form, verify it and store it.
+    pub fn add_json(&self, json: &str) -> Result<SelfAddressingIdentifier, MmioError> {
+        self.add(&parse_oca_bundle(json)?)
+    }
+
+    /// Load and verify the bundle `said`, or `None` if it is not stored.
+    pub fn get(
+        &self,
+        said: &SelfAddressingIdentifier,
+    ) -> Result<Option<OCABundleModel>, MmioError> {
+        let json = match fs::read_to_string(self.path(said)) {
+            Ok(json) => json,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let mut bundle = parse_oca_bundle(&json)?;
+        fill_overlay_defs(&mut bundle)?;
+        let actual = verify_bundle(&bundle)?;
+        if &actual != said {
+            return Err(MmioError::SaidMismatch {
+                expected: said.clone(),
+                actual,
+            });
+        }
+        Ok(Some(bundle))
+    }
+
+    /// Aliases of stored bundles with the SAID each names.
+    pub fn aliases(&self) -> Result<BTreeMap<String, SelfAddressingIdentifier>, MmioError> {
+        match fs::read_to_string(self.root.join(ALIASES_FILE)) {
+            Ok(json) => Ok(serde_json::from_str(&json)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(BTreeMap::new()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Name the stored bundle `said` by `alias`, of the form
+    /// `name@version`, replacing what the alias named before.
+    pub fn add_alias(&self, alias: &str, said: &SelfAddressingIdentifier) -> Result<(), MmioError> {
+        check_alias(alias)?;
+        if !self.path(said).is_file() {
+            return Err(MmioError::InvalidSemantics(format!(
+                "bundle {} is not in the store",
+                said
+            )));
+        }
+        let mut aliases = self.aliases()?;
+        aliases.insert(alias.to_string(), said.clone());
+        fs::write(
+            self.root.join(ALIASES_FILE),
+            serde_json::to_string_pretty(&aliases)?,
+        )?;
+        Ok(())
+    }
+
+    /// SAIDs of the stored bundles, sorted. Other files are ignored.
+    pub fn list(&self) -> Result<Vec<SelfAddressingIdentifier>, MmioError> {
+        let mut saids = vec![];
+        for entry in fs::read_dir(&self.root)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|e| e == "json") {
+                if let Some(said) = path
+                    .file_stem()
+                    .and_then(|s| s.to_str())
+                    .and_then(|s| s.parse().ok())
+                {
+                    saids.push(said);
+                }
+            }
+        }
+        saids.sort_by_key(|s: &SelfAddressingIdentifier| s.to_string());
+        Ok(saids)
+    }
+}
+
+impl BundleResolver for BundleStore {
+    fn resolve(
+        &self,
+        said: &SelfAddressingIdentifier,
+    ) -> Result<Option<OCABundleModel>, MmioError> {
+        self.get(said)
+    }
+
+    fn resolve_alias(&self, alias: &str) -> Result<Option<SelfAddressingIdentifier>, MmioError> {
+        Ok(self.aliases()?.remove(alias))
+    }
+}
+
+impl MMIO {
+    /// Replace the bundle references of all modalities with the bundles
+    /// `resolver` finds for them. Nothing changes unless every reference
+    /// resolves. Returns the number of modalities resolved.
+    pub fn resolve_semantics(&mut self, resolver: &dyn BundleResolver) -> Result<usize, MmioError> {
+        let mut resolved = vec![];
+        for (index, modality) in self.modalities.iter().enumerate() {
+            if let Semantic::Reference(said) = &modality.oca_bundle {
+                let bundle = resolver.resolve(said)?.ok_or_else(|| {
+                    MmioError::InvalidSemantics(format!(
+                        "bundle {} of modality {} not found",
+                        said, index
+                    ))
+                })?;
+                resolved.push((index, bundle));
+            }
+        }
+        let count = resolved.len();
+        for (index, bundle) in resolved {
+            self.set_semantic(index, Semantic::Bundle(bundle))?;
+        }
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use said::derivation::HashFunction;
+
+    use super::*;
+    use crate::{compile_ocafile, Modality, ModalityType, ARROW_MEDIA_TYPE, DEFAULT_HASH};
+
+    #[test]
+    fn test_bundle_store() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let store = BundleStore::open(dir).unwrap();
+        let fake = compile_ocafile(include_str!("../docs/examples/assets/fake.ocafile")).unwrap();
+        let mock = compile_ocafile(include_str!("../docs/examples/assets/mock.ocafile")).unwrap();
+        let fake_said = store.add(&fake).unwrap();
+        assert_eq!(Some(&fake_said), fake.digest.as_ref());
+        // Published JSON, as written to the store, adds under the same SAID
+        let published = std::fs::read_to_string(store.path(&fake_said)).unwrap();
+        assert_eq!(store.add_json(&published).unwrap(), fake_said);
+        let mock_said = store.add(&mock).unwrap();
+        std::fs::write(dir.join("notes.txt"), "not a bundle").unwrap();
+
+        let mut listed = vec![fake_said.clone(), mock_said.clone()];
+        listed.sort_by_key(|s| s.to_string());
+        assert_eq!(store.list().unwrap(), listed);
+        let loaded = store.get(&fake_said).unwrap().unwrap();
+        assert_eq!(loaded.digest, fake.digest);
+        assert_eq!(loaded.capture_base.attributes, fake.capture_base.attributes);
+        let unknown = HashFunction::from(DEFAULT_HASH).derive(b"unknown");
+        assert!(store.get(&unknown).unwrap().is_none());
+
+        // Aliases name stored bundles by name@version
+        store.add_alias("Mock@1.0", &mock_said).unwrap();
+        assert_eq!(store.list().unwrap(), listed);
+        assert_eq!(
+            resolve_bundle_id("Mock@1.0", &store).unwrap(),
+            mock_said.clone()
+        );
+        assert_eq!(
+            resolve_bundle_id(&fake_said.to_string(), &store).unwrap(),
+            fake_said.clone()
+        );
+        assert!(matches!(
+            resolve_bundle_id("Mock@2.0", &store),
+            Err(MmioError::InvalidSemantics(_))
+        ));
+        assert!(matches!(
+            store.add_alias("Mock", &mock_said),
+            Err(MmioError::InvalidSemantics(_))
+        ));
+        assert!(matches!(
+            store.add_alias("Unknown@1.0", &unknown),
+            Err(MmioError::InvalidSemantics(_))
+        ));
+
+        let mut mmio = MMIO::new();
+        for said in [&fake_said, &mock_said] {
+            mmio.add_modality(Modality {
+                digest: None,
+                modality_said: None,
+                modality_type: ModalityType::Tabular,
+                media_type: ARROW_MEDIA_TYPE.to_string(),
+                oca_bundle: Semantic::Reference(said.clone()),
+                locator: None,
+            })
+            .unwrap();
+        }
+        let mut unresolved = mmio.clone();
+        unresolved.modalities[1].oca_bundle = Semantic::Reference(unknown);
+        assert!(matches!(
+            unresolved.resolve_semantics(&store),
+            Err(MmioError::InvalidSemantics(_))
+        ));
+        assert!(matches!(
+            unresolved.modalities[0].oca_bundle,
+            Semantic::Reference(_)
+        ));
+        assert_eq!(mmio.resolve_semantics(&store).unwrap(), 2);
+        assert!(matches!(
+            &mmio.modalities[1].oca_bundle,
+            Semantic::Bundle(b) if b.digest == mock.digest
+        ));
+        assert_eq!(mmio.resolve_semantics(&store).unwrap(), 0);
+
+        // Bundles that do not match their SAID are rejected
+        let tampered = published.replace("FAKE Patient", "Tampered");
+        assert!(matches!(
+            store.add_json(&tampered),
+            Err(MmioError::SaidMismatch { .. })
+        ));
+        std::fs::write(store.path(&fake_said), &tampered).unwrap();
+        assert!(matches!(
+            store.get(&fake_said),
+            Err(MmioError::SaidMismatch { .. })
+        ));
+        std::fs::copy(store.path(&mock_said), store.path(&fake_said)).unwrap();
+        assert!(matches!(
+            store.get(&fake_said),
+            Err(MmioError::SaidMismatch { .. })
+        ));
+    }
+}
@@ -0,0 +1,420 @@
+use std::collections::{BTreeSet, HashMap};
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime};
+use oca_ast::ast::{
+    CaptureContent, Command, CommandType, NestedValue, OCAAst, ObjectKind, OverlayContent,
+};
+use oca_bundle::build::{from_ast, Error as BuildError};
+use oca_bundle::state::oca_bundle::overlay::OverlayModel;
+use oca_sdk_rs::ocafile::error::ParseError;
+use oca_sdk_rs::ocafile::parse_from_string;
+use oca_sdk_rs::{AttributeType, NestedAttrType, OCABundleModel};
+use overlay_file::overlay_registry::OverlayRegistry;
+use overlay_file::{OverlayDef, OverlayFile};
+use polars::prelude::{Column, DataFrame, DataType};
+
+use crate::{MmioError, Modality, ModalityType, Semantic, ARROW_MEDIA_TYPE, MMIO};
+
+/// Text columns with at most this many distinct values, each occurring at
+/// least twice on average, are treated as categorical.
+const MAX_CATEGORIES: usize = 10;
+
+lazy_static::lazy_static! {
+    /// Definitions of the core overlays, as shipped with `overlay-file`, and
+    /// of the link overlay used for transformations.
+    pub(crate) static ref OVERLAYS: Overlays = Overlays {
+        files: vec![
+            ("semantic", parse_overlays(include_str!("core.overlayfile"))),
+            ("transformation", parse_overlays(include_str!("link.overlayfile"))),
+        ],
+    };
+}
+
+fn parse_overlays(source: &str) -> OverlayFile {
+    overlay_file::parse_from_string(source.to_string()).expect("overlay definitions are valid")
+}
+
+/// Overlay registry holding the overlay definitions known to this crate.
+pub(crate) struct Overlays {
+    files: Vec<(&'static str, OverlayFile)>,
+}
+
+impl Overlays {
+    fn definitions(&self) -> impl Iterator<Item = &OverlayDef> {
+        self.files.iter().flat_map(|(_, file)| &file.overlays_def)
+    }
+}
+
+impl OverlayRegistry for Overlays {
+    fn get_by_filename(&self, name: &str) -> Option<&OverlayFile> {
+        self.files
+            .iter()
+            .find(|(file_name, _)| *file_name == name)
+            .map(|(_, file)| file)
+    }
+
+    /// Look up `name`, `overlay/name` or `name/version`, ignoring case.
+    fn get_overlay(&self, name: &str) -> Result<&OverlayDef, &'static str> {
+        let name = name.strip_prefix("overlay/").unwrap_or(name);
+        let (name, version) = match name.split_once('/') {
+            Some((name, version)) => (name, Some(version)),
+            None => (name, None),
+        };
+        self.definitions()
+            .find(|o| {
+                o.name.eq_ignore_ascii_case(name)
+                    && version.is_none_or(|v| o.version.eq_ignore_ascii_case(v))
+            })
+            .ok_or("Overlay definition not found in registry")
+    }
+
+    fn list_by_namespace(&self, _namespace: &str) -> Vec<&OverlayFile> {
+        vec![]
+    }
+
+    fn list_all(&self) -> Vec<String> {
+        self.definitions().map(|o| o.get_full_name()).collect()
+    }
+}
+
+/// `precompiler` of OCAfiles holding links between bundles, which are read
+/// with [`Link::from_ocafile`](crate::Link::from_ocafile).
+const TRANSFORMATION: &str = "transformation";
+
+impl Semantic {
+    /// Compile OCAfile source into a bundle embedded as
+    /// [`Semantic::Bundle`]. Errors give the line of the OCAfile they occur
+    /// at.
+    pub fn from_ocafile(source: &str) -> Result<Semantic, MmioError> {
+        compile_ocafile(source).map(Semantic::Bundle)
+    }
+}
+
+/// Compile OCAfile source into a bundle, using the core overlay
+/// definitions.
+pub fn compile_ocafile(source: &str) -> Result<OCABundleModel, MmioError> {
+    if let Some((line, _, _)) = ocafile_meta(source)
+        .into_iter()
+        .find(|(_, key, value)| key == "precompiler" && value == TRANSFORMATION)
+    {
+        return Err(MmioError::InvalidOcafile {
+            line,
+            message: "transformation OCAfiles hold links, not a bundle".into(),
+        });
+    }
+    let ast = parse_from_string(source.to_string(), &*OVERLAYS).map_err(|e| match e {
+        ParseError::GrammarError {
+            line_number,
+            message,
+            ..
+        } => MmioError::InvalidOcafile {
+            line: line_number,
+            message,
+        },
+        e => MmioError::InvalidOcafile {
+            line: failing_line(source),
+            message: e.to_string(),
+        },
+    })?;
+    from_ast(None, &ast)
+        .map(|build| build.oca_bundle)
+        .map_err(|errors| {
+            let line = errors
+                .iter()
+                .map(|BuildError::FromASTError { line_number, .. }| *line_number)
+                .next()
+                .unwrap_or_default();
+            let messages: Vec<_> = errors
+                .iter()
+                .map(|BuildError::FromASTError { message, .. }| message.as_str())
+                .collect();
+            MmioError::InvalidOcafile {
+                line,
+                message: messages.join(", "),
+            }
+        })
+}
+
+/// Meta comments of an OCAfile, such as `-- name=fake`, as line, key and
+/// value.
+pub(crate) fn ocafile_meta(source: &str) -> Vec<(usize, String, String)> {
+    source
+        .lines()
+        .enumerate()
+        .filter_map(|(i, line)| {
+            let (key, value) = line.trim().strip_prefix("--")?.split_once('=')?;
+            Some((i + 1, key.trim().to_string(), value.trim().to_string()))
+        })
+        .collect()
+}
+
+/// Line of the first instruction that fails to parse. The parser only
+/// locates grammar errors, so parse growing prefixes of the source until the
+/// error shows up, then go back to the unindented line starting the
+/// instruction.
+fn failing_line(source: &str) -> usize {
+    let lines: Vec<&str> = source.lines().collect();
+    let end = (1..=lines.len())
+        .find(|&n| {
+            let prefix = lines[..n].join("\n") + "\n";
+            matches!(
+                parse_from_string(prefix, &*OVERLAYS),
+                Err(e) if !matches!(e, ParseError::GrammarError { .. })
+            )
+        })
+        .unwrap_or(lines.len());
+    (1..=end)
+        .rev()
+        .find(|&n| !lines[n - 1].starts_with([' ', '\t']))
+        .unwrap_or(end)
+}
+
+/// Semantics inferred for one column.
+struct InferredAttribute {
+    name: String,
+    attr_type: NestedAttrType,
+    entry_codes: Option<Vec<String>>,
+}
+
+/// Infer an OCA bundle from the schema and values of `data`, with a
+/// capture base, character encoding and meta overlays, and entry codes for
+/// categorical columns.
+pub fn infer_bundle(data: &DataFrame, name: Option<&str>) -> Result<OCABundleModel, MmioError> {
+    let attributes = data
+        .get_columns()
+        .iter()
+        .map(infer_attribute)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut ast = OCAAst::new();
+    ast.commands.push(Command {
+        kind: CommandType::Add,
+        object_kind: ObjectKind::CaptureBase(CaptureContent {
+            attributes: Some(
+                attributes
+                    .iter()
+                    .map(|a| (a.name.clone(), a.attr_type.clone()))
+                    .collect(),
+            ),
+        }),
+    });
+    let name = name.unwrap_or("Inferred");
+    ast.commands.push(overlay(
+        "meta",
+        vec![
+            ("language", text("en")),
+            ("name", text(name)),
+            (
+                "description",
+                text(&format!("Inferred from {} columns", attributes.len())),
+            ),
+        ],
+    )?);
+    ast.commands.push(overlay(
+        "character_encoding",
+        vec![(
+            "attribute_character_encodings",
+            NestedValue::Object(
+                attributes
+                    .iter()
+                    .map(|a| (a.name.clone(), text("utf-8")))
+                    .collect(),
+            ),
+        )],
+    )?);
+    let entry_codes: Vec<_> = attributes
+        .iter()
+        .filter_map(|a| {
+            let codes = a.entry_codes.as_ref()?;
+            Some((
+                a.name.clone(),
+                NestedValue::Array(codes.iter().map(|c| text(c)).collect()),
+            ))
+        })
+        .collect();
+    if !entry_codes.is_empty() {
+        ast.commands.push(overlay(
+            "entry_code",
+            vec![(
+                "attribute_entry_codes",
+                NestedValue::Object(entry_codes.into_iter().collect()),
+            )],
+        )?);
+    }
+
+    build_bundle(None, &ast)
+}
+
+/// Add an overlay to a copy of `bundle` and recompute its digests.
+pub(crate) fn add_overlay(
+    bundle: &OCABundleModel,
+    name: &str,
+    properties: Vec<(&str, NestedValue)>,
+) -> Result<OCABundleModel, MmioError> {
+    let mut bundle = bundle.clone();
+    fill_overlay_defs(&mut bundle)?;
+    let mut ast = OCAAst::new();
+    ast.commands.push(overlay(name, properties)?);
+    build_bundle(Some(bundle), &ast)
+}
+
+/// Look up the definitions of overlays read from published JSON, which
+/// lack them.
+pub(crate) fn fill_overlay_defs(bundle: &mut OCABundleModel) -> Result<(), MmioError> {
+    for overlay in bundle.overlays.iter_mut() {
+        if overlay.overlay_def.is_none() {
+            let overlay_def = OVERLAYS
+                .get_overlay(&overlay.name)
+                .map_err(|e| MmioError::InvalidSemantics(format!("{}: {}", e, overlay.name)))?;
+            overlay.overlay_def = Some(overlay_def.clone());
+        }
+    }
+    Ok(())
+}
+
+/// Name of `overlay` without namespace and version, whether it was built
+/// here or read from published JSON, where it is `overlay/<name>/<version>`.
+pub(crate) fn overlay_name(overlay: &OverlayModel) -> &str {
+    if let Some(overlay_def) = &overlay.overlay_def {
+        return overlay_def.get_name();
+    }
+    let name = overlay
+        .name
+        .strip_prefix("overlay/")
+        .unwrap_or(&overlay.name);
+    name.split('/').next().unwrap_or(name)
+}
+
+/// Infer the semantics of `data` and return an MMIO with a tabular
+/// modality embedding them, ready for [`MMIO::ingest`].
+pub fn infer_semantics(data: &DataFrame, name: Option<&str>) -> Result<MMIO, MmioError> {
+    let bundle = infer_bundle(data, name)?;
+    let mut mmio = MMIO::new();
+    mmio.add_modality(Modality {
+        digest: None,
+        modality_said: None,
+        modality_type: ModalityType::Tabular,
+        media_type: ARROW_MEDIA_TYPE.to_string(),
+        oca_bundle: Semantic::Bundle(bundle),
+        locator: None,
+    })?;
+    mmio.compute_digests()?;
+    Ok(mmio)
+}
+
+fn build_bundle(from: Option<OCABundleModel>, ast: &OCAAst) -> Result<OCABundleModel, MmioError> {
+    from_ast(from, ast)
+        .map(|build| build.oca_bundle)
+        .map_err(|errors| {
+            let errors: Vec<_> = errors.iter().map(|e| e.to_string()).collect();
+            MmioError::InvalidSemantics(errors.join(", "))
+        })
+}
+
+fn overlay(name: &str, properties: Vec<(&str, NestedValue)>) -> Result<Command, MmioError> {
+    let overlay_def = OVERLAYS
+        .get_overlay(name)
+        .map_err(|e| MmioError::InvalidSemantics(format!("{}: {}", e, name)))?
+        .clone();
+    Ok(Command {
+        kind: CommandType::Add,
+        object_kind: ObjectKind::Overlay(OverlayContent {
+            properties: Some(
+                properties
+                    .into_iter()
+                    .map(|(k, v)| (k.to_string(), v))
+                    .collect(),
+            ),
+            overlay_def,
+        }),
+    })
+}
+
+pub(crate) fn text(s: &str) -> NestedValue {
+    NestedValue::Value(s.to_string())
+}
+
+fn infer_attribute(column: &Column) -> Result<InferredAttribute, MmioError> {
+    let dtype = column.dtype();
+    let (attr_type, entry_codes) = if dtype.is_categorical() || dtype.is_enum() {
+        let codes = distinct_values(column, usize::MAX)?;
+        (NestedAttrType::Value(AttributeType::Text), Some(codes))
+    } else if dtype.is_string() {
+        infer_text(column)?
+    } else {
+        (attribute_type(dtype), None)
+    };
+    Ok(InferredAttribute {
+        name: column.name().to_string(),
+        attr_type,
+        entry_codes,
+    })
+}
+
+/// Attribute type for values of `dtype`, from the type alone.
+fn attribute_type(dtype: &DataType) -> NestedAttrType {
+    let attr_type = match dtype {
+        DataType::List(item) => return NestedAttrType::Array(Box::new(attribute_type(item))),
+        DataType::Boolean => AttributeType::Boolean,
+        DataType::Date | DataType::Datetime(_, _) | DataType::Time => AttributeType::DateTime,
+        DataType::Duration(_) => AttributeType::Numeric,
+        DataType::Binary => AttributeType::Binary,
+        dtype if dtype.is_numeric() => AttributeType::Numeric,
+        _ => AttributeType::Text,
+    };
+    NestedAttrType::Value(attr_type)
+}
+
+/// Detect booleans, dates and categories kept as strings.
+fn infer_text(column: &Column) -> Result<(NestedAttrType, Option<Vec<String>>), MmioError> {
+    let values: Vec<&str> = column
+        .as_materialized_series()
+        .str()?
+        .into_iter()
+        .flatten()
+        .collect();
+    if values.is_empty() {
+        return Ok((NestedAttrType::Value(AttributeType::Text), None));
+    }
+    if values.iter().all(|v| is_boolean(v)) {
+        return Ok((NestedAttrType::Value(AttributeType::Boolean), None));
+    }
+    if values.iter().all(|v| is_date(v)) {
+        return Ok((NestedAttrType::Value(AttributeType::DateTime), None));
+    }
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for v in &values {
+        *counts.entry(v).or_default() += 1;
+    }
+    let categorical = counts.len() <= MAX_CATEGORIES && counts.len() * 2 <= values.len();
+    let entry_codes = categorical
+        .then(|| distinct_values(column, MAX_CATEGORIES))
+        .transpose()?;
+    Ok((NestedAttrType::Value(AttributeType::Text), entry_codes))
+}
+
+/// Sorted distinct non-null values of `column`, read as strings.
+fn distinct_values(column: &Column, limit: usize) -> Result<Vec<String>, MmioError> {
+    let strings = column.cast(&DataType::String)?;
+    let values: BTreeSet<String> = strings
+        .as_materialized_series()
+        .str()?
+        .into_iter()
+        .flatten()
+        .map(str::to_string)
+        .collect();
+    Ok(values.into_iter().take(limit).collect())
+}
+
+/// Whether `s` is `true` or `false`, ignoring case.
+pub(crate) fn is_boolean(s: &str) -> bool {
+    s.eq_ignore_ascii_case("true") || s.eq_ignore_ascii_case("false")
+}
+
+/// Whether `s` is an ISO 8601 date or date and time.
+pub(crate) fn is_date(s: &str) -> bool {
+    NaiveDate::parse_from_str(s, "%Y-%m-%d").is_ok()
+        || NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M:%S%.f").is_ok()
+        || NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S%.f").is_ok()
+        || DateTime::parse_from_rfc3339(s).is_ok()
+}
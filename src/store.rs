@@ -0,0 +1,328 @@
+//! Content-addressed store of modality payloads.
+//!
+//! Each payload is kept once, under its SAID, in a directory named after
+//! the first characters of the SAID:
+//!
+//! ```text
+//! <root>/EDY/EDYf1yQenKaoaWLb-vPPAvycLW70lk4TFy1I7fs4A1J0
+//! ```
+//!
+//! Payloads are written to `<root>/tmp` first and moved into place once
+//! hashed, so a payload is never visible under its SAID half written.
+
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use said::derivation::HashFunctionCode;
+use said::SelfAddressingIdentifier;
+
+use crate::hash::StreamHasher;
+use crate::{hash_code_of, hash_file, ContentStore, Locator, MmioError, PayloadReader, MMIO};
+
+/// Number of leading SAID characters naming the shard directory.
+const SHARD_LEN: usize = 3;
+
+const TMP_DIR: &str = "tmp";
+
+static TMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Result of checking every payload in a [`BlobStore`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FsckReport {
+    /// Number of payloads hashed.
+    pub checked: usize,
+    /// Payloads whose content no longer matches their SAID, with the SAID
+    /// computed from the content.
+    pub corrupted: Vec<(SelfAddressingIdentifier, SelfAddressingIdentifier)>,
+    /// Files that are not named after a SAID or sit in the wrong shard.
+    pub unexpected: Vec<PathBuf>,
+}
+
+impl FsckReport {
+    pub fn is_valid(&self) -> bool {
+        self.corrupted.is_empty() && self.unexpected.is_empty()
+    }
+}
+
+/// Directory of payloads keyed by their SAID.
+#[derive(Debug, Clone)]
+pub struct BlobStore {
+    root: PathBuf,
+}
+
+impl BlobStore {
+    /// Open the store at `root`, creating the directory if needed.
+    pub fn open(root: impl Into<PathBuf>) -> Result<Self, MmioError> {
+        let root = root.into();
+        fs::create_dir_all(root.join(TMP_DIR))?;
+        Ok(Self { root })
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Path the payload `said` is stored at.
+    pub fn path(&self, said: &SelfAddressingIdentifier) -> PathBuf {
+        let said = said.to_string();
+        self.root.join(&said[..SHARD_LEN]).join(said)
+    }
+
+    pub fn has(&self, said: &SelfAddressingIdentifier) -> bool {
+        self.path(said).is_file()
+    }
+
+    /// Hash the payload read from `reader` with `code` and store it, unless
+    /// a payload with the same SAID is stored already.
+    pub fn put<R: Read>(
+        &self,
+        mut reader: R,
+        code: &HashFunctionCode,
+    ) -> Result<SelfAddressingIdentifier, MmioError> {
+        let tmp = self.root.join(TMP_DIR).join(format!(
+            "{}-{}",
+            std::process::id(),
+            TMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        let result = (|| {
+            let mut file = File::create(&tmp)?;
+            let mut hasher = StreamHasher::new(code);
+            let mut buffer = [0u8; 65536];
+            loop {
+                let n = reader.read(&mut buffer)?;
+                if n == 0 {
+                    break;
+                }
+                hasher.update(&buffer[..n]);
+                file.write_all(&buffer[..n])?;
+            }
+            file.sync_all()?;
+            let said = hasher.finalize(code);
+            let path = self.path(&said);
+            if !path.is_file() {
+                fs::create_dir_all(path.parent().expect("payloads are in a shard"))?;
+                fs::rename(&tmp, &path)?;
+            }
+            Ok(said)
+        })();
+        // Gone already if the payload was moved into place
+        let _ = fs::remove_file(&tmp);
+        result
+    }
+
+    /// Store the content of the file at `path`.
+    pub fn put_file(
+        &self,
+        path: impl AsRef<Path>,
+        code: &HashFunctionCode,
+    ) -> Result<SelfAddressingIdentifier, MmioError> {
+        self.put(File::open(path)?, code)
+    }
+
+    /// Open the payload `said`, or `None` if it is not stored. The content
+    /// is checked against the SAID as it is read.
+    pub fn get(
+        &self,
+        said: &SelfAddressingIdentifier,
+    ) -> Result<Option<PayloadReader<'static>>, MmioError> {
+        match File::open(self.path(said)) {
+            Ok(file) => Ok(Some(PayloadReader::new(Box::new(file), said.clone()))),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Remove the payload `said`. Returns whether it was stored.
+    pub fn remove(&self, said: &SelfAddressingIdentifier) -> Result<bool, MmioError> {
+        match fs::remove_file(self.path(said)) {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Files in the shard directories, with the SAID each is named after if
+    /// it is stored where that SAID belongs.
+    fn files(&self) -> Result<Vec<(PathBuf, Option<SelfAddressingIdentifier>)>, MmioError> {
+        let mut files = vec![];
+        for shard in fs::read_dir(&self.root)? {
+            let shard = shard?.path();
+            if !shard.is_dir() {
+                files.push((shard, None));
+                continue;
+            }
+            if shard.file_name().is_some_and(|n| n == TMP_DIR) {
+                continue;
+            }
+            for entry in fs::read_dir(&shard)? {
+                let path = entry?.path();
+                let said = path
+                    .file_name()
+                    .and_then(|n| n.to_str())
+                    .and_then(|n| n.parse::<SelfAddressingIdentifier>().ok())
+                    .filter(|said| self.path(said) == path);
+                files.push((path, said));
+            }
+        }
+        files.sort_by(|(a, _), (b, _)| a.cmp(b));
+        Ok(files)
+    }
+
+    /// SAIDs of the stored payloads, sorted.
+    pub fn list(&self) -> Result<Vec<SelfAddressingIdentifier>, MmioError> {
+        Ok(self
+            .files()?
+            .into_iter()
+            .filter_map(|(_, said)| said)
+            .collect())
+    }
+
+    /// Hash every stored payload and compare it with its SAID.
+    pub fn fsck(&self) -> Result<FsckReport, MmioError> {
+        let mut report = FsckReport::default();
+        for (path, said) in self.files()? {
+            let Some(said) = said else {
+                report.unexpected.push(path);
+                continue;
+            };
+            let actual = hash_file(&path, &hash_code_of(&said))?;
+            report.checked += 1;
+            if actual != said {
+                report.corrupted.push((said, actual));
+            }
+        }
+        Ok(report)
+    }
+
+    /// Stored payloads none of `mmios` refers to, by `modality_said` or by
+    /// a store locator.
+    pub fn unreferenced(&self, mmios: &[MMIO]) -> Result<Vec<SelfAddressingIdentifier>, MmioError> {
+        let mut referenced = HashSet::new();
+        for modality in mmios.iter().flat_map(|m| &m.modalities) {
+            referenced.extend(modality.modality_said.clone());
+            if let Some(Locator::Store(key)) = &modality.locator {
+                referenced.insert(key.clone());
+            }
+        }
+        Ok(self
+            .list()?
+            .into_iter()
+            .filter(|said| !referenced.contains(said))
+            .collect())
+    }
+
+    /// Remove the payloads none of `mmios` refers to and return their
+    /// SAIDs.
+    pub fn gc(&self, mmios: &[MMIO]) -> Result<Vec<SelfAddressingIdentifier>, MmioError> {
+        let removed = self.unreferenced(mmios)?;
+        for said in &removed {
+            self.remove(said)?;
+        }
+        Ok(removed)
+    }
+}
+
+impl ContentStore for BlobStore {
+    fn open<'a>(
+        &'a self,
+        key: &SelfAddressingIdentifier,
+    ) -> Result<Option<Box<dyn Read + 'a>>, MmioError> {
+        Ok(self
+            .get(key)?
+            .map(|reader| Box::new(reader) as Box<dyn Read + 'a>))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use said::derivation::HashFunction;
+
+    use super::*;
+    use crate::{Modality, PayloadContext, Semantic, DEFAULT_HASH};
+
+    #[test]
+    fn test_blob_store() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let store = BlobStore::open(dir).unwrap();
+        let scan = b"\x89PNG\r\n\x1a\nscan".to_vec();
+        let notes = b"notes".to_vec();
+
+        let said = store.put(scan.as_slice(), &DEFAULT_HASH).unwrap();
+        let modality = Modality::from_reader(
+            scan.as_slice(),
+            Semantic::Reference(said.clone()),
+            &Default::default(),
+        )
+        .unwrap();
+        assert_eq!(modality.modality_said.as_ref(), Some(&said));
+        // The same payload is stored once
+        assert_eq!(store.put(scan.as_slice(), &DEFAULT_HASH).unwrap(), said);
+        let sha = store
+            .put(scan.as_slice(), &HashFunctionCode::SHA2_256)
+            .unwrap();
+        let notes_said = store.put(notes.as_slice(), &DEFAULT_HASH).unwrap();
+        assert!(store.has(&said) && store.has(&sha));
+        assert!(store
+            .path(&said)
+            .starts_with(dir.join(&said.to_string()[..3])));
+        let mut listed = vec![said.clone(), sha.clone(), notes_said.clone()];
+        listed.sort_by_key(|s| s.to_string());
+        assert_eq!(store.list().unwrap(), listed);
+        assert_eq!(
+            store.get(&said).unwrap().unwrap().read_verified().unwrap(),
+            scan
+        );
+        let unknown = HashFunction::from(DEFAULT_HASH).derive(b"unknown");
+        assert!(store.get(&unknown).unwrap().is_none());
+        assert!(!store.has(&unknown));
+
+        // Stored payloads can be located by their key
+        let mut located = modality.clone();
+        located.locator = Some(Locator::Store(said.clone()));
+        let context = PayloadContext {
+            base_dir: None,
+            store: Some(&store),
+        };
+        assert_eq!(
+            located
+                .open_payload(&context)
+                .unwrap()
+                .read_verified()
+                .unwrap(),
+            scan
+        );
+
+        let report = store.fsck().unwrap();
+        assert!(report.is_valid());
+        assert_eq!(report.checked, 3);
+        std::fs::write(store.path(&notes_said), b"edited").unwrap();
+        std::fs::write(dir.join("stray"), b"").unwrap();
+        let report = store.fsck().unwrap();
+        assert!(!report.is_valid());
+        assert_eq!(report.corrupted.len(), 1);
+        assert_eq!(report.corrupted[0].0, notes_said);
+        assert_eq!(report.unexpected, vec![dir.join("stray")]);
+        assert!(matches!(
+            store.get(&notes_said).unwrap().unwrap().read_verified(),
+            Err(MmioError::SaidMismatch { .. })
+        ));
+
+        // Garbage collection keeps what the MMIOs refer to
+        let mut mmio = MMIO::new();
+        mmio.add_modality(modality).unwrap();
+        let mut by_locator = MMIO::new();
+        located.modality_said = None;
+        by_locator.add_modality(located).unwrap();
+        assert_eq!(store.unreferenced(&[mmio.clone()]).unwrap().len(), 2);
+        let mut removed = store.gc(&[mmio, by_locator]).unwrap();
+        removed.sort_by_key(|s| s.to_string());
+        let mut expected = vec![sha, notes_said];
+        expected.sort_by_key(|s| s.to_string());
+        assert_eq!(removed, expected);
+        assert_eq!(store.list().unwrap(), vec![said]);
+    }
+}
@@ -0,0 +1,278 @@
+//! Transformation of records between OCA bundles along link overlays.
+//!
+//! A link overlay names a target bundle and links attributes of its own
+//! capture base to attributes of the target. A column linked on its own is
+//! renamed. Text columns linked to the same target attribute are
+//! concatenated, separated by spaces; other columns cannot share a target.
+//! Columns without a link are dropped, and every target attribute must be
+//! in the capture base of the target bundle.
+
+use oca_ast::ast::{NestedValue, RefValue};
+use oca_bundle::state::oca_bundle::overlay::OverlayModel;
+use oca_sdk_rs::OCABundleModel;
+use polars::prelude::{Column, DataFrame, IntoColumn, NamedFrom, PlSmallStr, Series};
+use said::derivation::HashFunction;
+use said::SelfAddressingIdentifier;
+
+use crate::semantics::{add_overlay, ocafile_meta, overlay_name, text};
+use crate::{
+    hash_code_of, parse_said, Event, MmioError, Modality, ModalityType, Semantic, TabularData,
+    TransformEvent, ARROW_MEDIA_TYPE, DEFAULT_HASH, MMIO,
+};
+
+/// Name of the overlay linking attributes to those of another bundle.
+pub const LINK_OVERLAY: &str = "link";
+
+/// Links from the attributes of a bundle to those of a target bundle.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Link {
+    pub target: SelfAddressingIdentifier,
+    /// Source attributes with the target attribute each links to, in order.
+    pub attributes: Vec<(String, String)>,
+}
+
+impl Link {
+    pub fn new(target: SelfAddressingIdentifier, attributes: Vec<(String, String)>) -> Self {
+        Self { target, attributes }
+    }
+
+    /// Read links from a transformation OCAfile, where a `target` meta
+    /// comment gives the target bundle as `refs:<SAID>` and each
+    /// `LINK ATTRIBUTE <source> -> <target>` line links one attribute.
+    pub fn from_ocafile(source: &str) -> Result<Self, MmioError> {
+        let meta = ocafile_meta(source);
+        let mut target = None;
+        for (line, key, value) in &meta {
+            if key == "target" {
+                let said = value.strip_prefix("refs:").unwrap_or(value);
+                target = Some(parse_said(said).map_err(|e| MmioError::InvalidOcafile {
+                    line: *line,
+                    message: e.to_string(),
+                })?);
+            }
+        }
+        let mut attributes = vec![];
+        for (i, line) in source.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with("--") || line.starts_with('#') {
+                continue;
+            }
+            let link = strip_prefix_ignore_case(line, "LINK ATTRIBUTE")
+                .and_then(|rest| rest.split_once("->"))
+                .map(|(source, target)| (source.trim(), target.trim()))
+                .filter(|(source, target)| !source.is_empty() && !target.is_empty());
+            match link {
+                Some((source, target)) => attributes.push((source.to_string(), target.to_string())),
+                None => {
+                    return Err(MmioError::InvalidOcafile {
+                        line: i + 1,
+                        message: "expected LINK ATTRIBUTE <source> -> <target>".into(),
+                    })
+                }
+            }
+        }
+        let target = target.ok_or_else(|| MmioError::InvalidOcafile {
+            line: 1,
+            message: "no target=refs:<SAID> meta comment".into(),
+        })?;
+        Ok(Link { target, attributes })
+    }
+
+    /// Read a link overlay. Returns `None` for overlays of other kinds.
+    pub fn from_overlay(overlay: &OverlayModel) -> Result<Option<Self>, MmioError> {
+        if overlay_name(overlay) != LINK_OVERLAY {
+            return Ok(None);
+        }
+        let property = |name| overlay.properties.as_ref().and_then(|p| p.get(name));
+        let target = match property("target") {
+            Some(NestedValue::Value(said)) => {
+                parse_said(said.strip_prefix("refs:").unwrap_or(said))?
+            }
+            Some(NestedValue::Reference(RefValue::Said(said))) => said.clone(),
+            _ => {
+                return Err(MmioError::InvalidSemantics(
+                    "link overlay has no target bundle SAID".into(),
+                ))
+            }
+        };
+        let Some(NestedValue::Object(links)) = property("attribute_links") else {
+            return Err(MmioError::InvalidSemantics(
+                "link overlay has no attribute_links".into(),
+            ));
+        };
+        let attributes = links
+            .iter()
+            .map(|(source, target)| match target {
+                NestedValue::Value(target) => Ok((source.clone(), target.clone())),
+                _ => Err(MmioError::InvalidSemantics(format!(
+                    "attribute '{}' is not linked to an attribute name",
+                    source
+                ))),
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Some(Link { target, attributes }))
+    }
+
+    /// All links in the overlays of `bundle`.
+    pub fn in_bundle(bundle: &OCABundleModel) -> Result<Vec<Self>, MmioError> {
+        bundle
+            .overlays
+            .iter()
+            .filter_map(|o| Link::from_overlay(o).transpose())
+            .collect()
+    }
+
+    /// Apply the links to `records`, giving one column per target attribute
+    /// in the order they are first linked.
+    pub fn apply(&self, records: &DataFrame) -> Result<DataFrame, MmioError> {
+        let mut targets: Vec<(&str, Vec<&Column>)> = vec![];
+        for (source, target) in &self.attributes {
+            let column = records.column(source).map_err(|_| {
+                MmioError::InvalidRecords(format!(
+                    "column '{}' linked to '{}' is missing",
+                    source, target
+                ))
+            })?;
+            match targets.iter_mut().find(|(t, _)| t == target) {
+                Some((_, sources)) => sources.push(column),
+                None => targets.push((target, vec![column])),
+            }
+        }
+        let columns = targets
+            .into_iter()
+            .map(|(target, sources)| combine(target, &sources))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(DataFrame::new(columns)?)
+    }
+}
+
+fn strip_prefix_ignore_case<'a>(s: &'a str, prefix: &str) -> Option<&'a str> {
+    let head = s.get(..prefix.len())?;
+    head.eq_ignore_ascii_case(prefix)
+        .then(|| &s[prefix.len()..])
+}
+
+fn combine(target: &str, sources: &[&Column]) -> Result<Column, MmioError> {
+    let name = PlSmallStr::from(target);
+    if let [column] = sources {
+        return Ok((*column).clone().with_name(name));
+    }
+    if !sources.iter().all(|c| c.dtype().is_string()) {
+        return Err(MmioError::InvalidSemantics(format!(
+            "only text attributes can be linked to the same attribute '{}'",
+            target
+        )));
+    }
+    let parts = sources
+        .iter()
+        .map(|c| c.as_materialized_series().str())
+        .collect::<Result<Vec<_>, _>>()?;
+    let joined: Vec<Option<String>> = (0..sources[0].len())
+        .map(|i| {
+            let values: Vec<&str> = parts.iter().filter_map(|p| p.get(i)).collect();
+            (!values.is_empty()).then(|| values.join(" "))
+        })
+        .collect();
+    Ok(Series::new(name, joined).into_column())
+}
+
+impl MMIO {
+    /// Link attributes of the bundle embedded in the tabular modality to
+    /// those of another bundle, adding a link overlay to it.
+    pub fn link(&mut self, link: &Link) -> Result<(), MmioError> {
+        let index = match &self.data {
+            Some(data) => data.modality,
+            None => self.tabular_modality()?,
+        };
+        self.link_modality(index, link)
+    }
+
+    /// Link attributes of the bundle embedded in the modality at `index`,
+    /// as [`MMIO::link`] does for the tabular modality.
+    pub fn link_modality(&mut self, index: usize, link: &Link) -> Result<(), MmioError> {
+        let bundle = self.embedded_bundle(index)?;
+        let attributes = &bundle.capture_base.attributes;
+        if let Some((source, _)) = link
+            .attributes
+            .iter()
+            .find(|(source, _)| !attributes.contains_key(source))
+        {
+            return Err(MmioError::InvalidSemantics(format!(
+                "cannot link '{}', it is not in the capture base",
+                source
+            )));
+        }
+        let links = link
+            .attributes
+            .iter()
+            .map(|(source, target)| (source.clone(), text(target)))
+            .collect();
+        let linked = add_overlay(
+            bundle,
+            LINK_OVERLAY,
+            vec![
+                ("target", text(&link.target.to_string())),
+                ("attribute_links", NestedValue::Object(links)),
+            ],
+        )?;
+        self.set_semantic(index, Semantic::Bundle(linked))
+    }
+
+    /// Transform the ingested records along the links to bundle `target`.
+    ///
+    /// Returns a new MMIO with a tabular modality holding the transformed
+    /// records and referencing the target bundle. Its provenance log
+    /// continues this one with a [`TransformEvent`].
+    pub fn transform(&self, target: &OCABundleModel) -> Result<MMIO, MmioError> {
+        let attributes = &target.capture_base.attributes;
+        let target = target
+            .digest
+            .as_ref()
+            .ok_or_else(|| MmioError::InvalidSemantics("the target bundle has no digest".into()))?;
+        let data = self
+            .data
+            .as_ref()
+            .ok_or_else(|| MmioError::InvalidModality("no records to transform".into()))?;
+        let bundle = self.embedded_bundle(data.modality)?;
+        let link = Link::in_bundle(bundle)?
+            .into_iter()
+            .find(|link| &link.target == target)
+            .ok_or_else(|| MmioError::InvalidSemantics(format!("no links to bundle {}", target)))?;
+        let transformed = TabularData::new(0, link.apply(&data.records)?);
+        if let Some(column) = transformed
+            .records
+            .get_column_names()
+            .into_iter()
+            .find(|c| !attributes.contains_key(c.as_str()))
+        {
+            return Err(MmioError::InvalidSemantics(format!(
+                "'{}' is not an attribute of bundle {}",
+                column, target
+            )));
+        }
+
+        let code = self.modalities[data.modality]
+            .modality_said
+            .as_ref()
+            .map_or(DEFAULT_HASH, hash_code_of);
+        let mut mmio = MMIO::new();
+        mmio.log = self.log.clone();
+        mmio.log.add_event(Event::Transform(TransformEvent::linked(
+            bundle.digest.clone(),
+            target.clone(),
+            &data.records,
+            &transformed.records,
+        )?))?;
+        mmio.add_modality(Modality {
+            digest: None,
+            modality_said: Some(HashFunction::from(code).derive(&transformed.to_ipc()?)),
+            modality_type: ModalityType::Tabular,
+            media_type: ARROW_MEDIA_TYPE.to_string(),
+            oca_bundle: Semantic::Reference(target.clone()),
+            locator: None,
+        })?;
+        mmio.data = Some(transformed);
+        mmio.compute_digests()?;
+        Ok(mmio)
+    }
+}
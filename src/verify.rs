@@ -0,0 +1,152 @@
+use pyo3::prelude::*;
+use said::SelfAddressingIdentifier;
+
+use crate::{hash_code_of, MmioError, DEFAULT_HASH, MMIO};
+
+/// A digest that does not match the content it is supposed to commit to.
+#[pyclass]
+#[derive(Debug, Clone, PartialEq)]
+pub struct DigestFailure {
+    /// Position in `MMIO.modalities`, or `None` for the top-level MMIO digest.
+    pub index: Option<usize>,
+    /// Digest stored in the document, `None` if it was missing.
+    pub expected: Option<SelfAddressingIdentifier>,
+    /// Digest recomputed from the content.
+    pub actual: SelfAddressingIdentifier,
+}
+
+impl DigestFailure {
+    pub fn is_missing(&self) -> bool {
+        self.expected.is_none()
+    }
+}
+
+impl std::fmt::Display for DigestFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let subject = match self.index {
+            Some(i) => format!("modality {}", i),
+            None => "MMIO".to_string(),
+        };
+        match &self.expected {
+            Some(expected) => write!(
+                f,
+                "SAID mismatch for {}: expected {}, computed {}",
+                subject, expected, self.actual
+            ),
+            None => write!(f, "No SAID found for {}, computed {}", subject, self.actual),
+        }
+    }
+}
+
+#[pymethods]
+impl DigestFailure {
+    #[getter]
+    fn get_index(&self) -> Option<usize> {
+        self.index
+    }
+
+    #[getter]
+    fn get_expected(&self) -> Option<String> {
+        self.expected.as_ref().map(|said| said.to_string())
+    }
+
+    #[getter]
+    fn get_actual(&self) -> String {
+        self.actual.to_string()
+    }
+
+    #[getter]
+    fn get_missing(&self) -> bool {
+        self.is_missing()
+    }
+
+    fn __str__(&self) -> String {
+        self.to_string()
+    }
+}
+
+/// Result of checking every digest of an MMIO against its content.
+#[pyclass]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct VerificationReport {
+    /// Failing modalities, in document order.
+    pub modalities: Vec<DigestFailure>,
+    /// Failure of the top-level MMIO digest, if any.
+    pub mmio: Option<DigestFailure>,
+}
+
+impl VerificationReport {
+    pub fn is_valid(&self) -> bool {
+        self.modalities.is_empty() && self.mmio.is_none()
+    }
+
+    /// All failures, modalities first.
+    pub fn failures(&self) -> impl Iterator<Item = &DigestFailure> {
+        self.modalities.iter().chain(self.mmio.iter())
+    }
+
+    /// Turn the report into an error carrying the first mismatching digest.
+    /// Missing digests are reported as `SaidDerivation` errors.
+    pub fn into_result(self) -> Result<(), MmioError> {
+        match self.failures().next() {
+            None => Ok(()),
+            Some(DigestFailure {
+                expected: Some(expected),
+                actual,
+                ..
+            }) => Err(MmioError::SaidMismatch {
+                expected: expected.clone(),
+                actual: actual.clone(),
+            }),
+            Some(failure) => Err(MmioError::SaidDerivation(failure.to_string())),
+        }
+    }
+}
+
+#[pymethods]
+impl VerificationReport {
+    #[getter(is_valid)]
+    fn py_is_valid(&self) -> bool {
+        self.is_valid()
+    }
+
+    #[getter(failures)]
+    fn py_failures(&self) -> Vec<DigestFailure> {
+        self.failures().cloned().collect()
+    }
+
+    fn __bool__(&self) -> bool {
+        self.is_valid()
+    }
+}
+
+impl MMIO {
+    /// Recompute the digest of every modality and of the MMIO itself and
+    /// compare them with the ones stored in the document.
+    pub fn verify(&self) -> Result<VerificationReport, MmioError> {
+        let mut report = VerificationReport::default();
+
+        for (index, modality) in self.modalities.iter().enumerate() {
+            let code = modality.digest.as_ref().map_or(DEFAULT_HASH, hash_code_of);
+            let actual = modality.clone().compute_digest_with(code, &self.version)?;
+            if modality.digest.as_ref() != Some(&actual) {
+                report.modalities.push(DigestFailure {
+                    index: Some(index),
+                    expected: modality.digest.clone(),
+                    actual,
+                });
+            }
+        }
+
+        let actual = self.clone().compute_digest()?;
+        if self.digest.as_ref() != Some(&actual) {
+            report.mmio = Some(DigestFailure {
+                index: None,
+                expected: self.digest.clone(),
+                actual,
+            });
+        }
+
+        Ok(report)
+    }
+}
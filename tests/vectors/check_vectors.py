@@ -0,0 +1,112 @@
+"""Independent Python check of the MMIO canonicalization test vectors.
+
+Run with `python3 tests/vectors/check_vectors.py`. Blake3 vectors are
+checked only when the `blake3` package is installed.
+"""
+
+import base64
+import hashlib
+import json
+import math
+import pathlib
+import sys
+from decimal import Decimal
+
+HERE = pathlib.Path(__file__).parent
+
+HASHES = {
+    "blake2b-256": ("F", lambda b: hashlib.blake2b(b, digest_size=32).digest()),
+    "blake2s-256": ("G", lambda b: hashlib.blake2s(b).digest()),
+    "sha3-256": ("H", lambda b: hashlib.sha3_256(b).digest()),
+    "sha2-256": ("I", lambda b: hashlib.sha256(b).digest()),
+}
+try:
+    import blake3
+
+    HASHES["blake3-256"] = ("E", lambda b: blake3.blake3(b).digest())
+except ImportError:
+    pass
+
+
+def format_number(x):
+    """ECMAScript Number.prototype.toString for finite doubles."""
+    x = float(x)
+    if x == 0:
+        return "0"
+    if x < 0:
+        return "-" + format_number(-x)
+    # repr gives the shortest round-trip digits
+    _, digits, exp = Decimal(repr(x)).normalize().as_tuple()
+    digits = "".join(map(str, digits))
+    k = len(digits)
+    n = k + exp
+    if k <= n <= 21:
+        return digits + "0" * (n - k)
+    if 0 < n <= 21:
+        return digits[:n] + "." + digits[n:]
+    if -6 < n <= 0:
+        return "0." + "0" * -n + digits
+    e = n - 1
+    return digits[0] + ("." + digits[1:] if k > 1 else "") + "e" + ("-" if e < 0 else "+") + str(abs(e))
+
+
+def canonicalize(value):
+    if value is None:
+        return "null"
+    if value is True:
+        return "true"
+    if value is False:
+        return "false"
+    if isinstance(value, (int, float)):
+        assert math.isfinite(value)
+        return format_number(value)
+    if isinstance(value, str):
+        return json.dumps(value, ensure_ascii=False)
+    if isinstance(value, list):
+        return "[" + ",".join(canonicalize(v) for v in value) + "]"
+    items = sorted(value.items(), key=lambda kv: kv[0].encode("utf-16-be"))
+    return "{" + ",".join(json.dumps(k, ensure_ascii=False) + ":" + canonicalize(v) for k, v in items) + "}"
+
+
+def said(code, digest):
+    # CESR: left-pad the raw digest so it encodes without padding, then
+    # replace the leading pad characters with the derivation code
+    pad = (3 - len(digest) % 3) % 3
+    encoded = base64.urlsafe_b64encode(b"\0" * pad + digest).decode()
+    return code + encoded[len(code):]
+
+
+def main():
+    failures = 0
+    for vector in json.loads((HERE / "canonical_json.json").read_text()):
+        actual = canonicalize(json.loads(vector["input"]))
+        if actual != vector["canonical"]:
+            failures += 1
+            print("canonical mismatch:", vector["input"], actual)
+
+    for vector in json.loads((HERE / "said.json").read_text()):
+        obj = dict(vector["object"])
+        # Locators are not part of the digest input
+        obj.pop("locator", None)
+        if "modalities" in obj:
+            obj["modalities"] = [{k: v for k, v in m.items() if k != "locator"} for m in obj["modalities"]]
+        code, fn = HASHES.get(vector["hash"], (None, None))
+        obj["digest"] = "#" * 44
+        digest_input = canonicalize(obj)
+        if digest_input != vector["digest_input"]:
+            failures += 1
+            print("digest input mismatch:", vector["description"])
+        if fn is None:
+            print("skipped", vector["hash"], "-", vector["description"])
+            continue
+        actual = said(code, fn(digest_input.encode()))
+        if actual != vector["said"]:
+            failures += 1
+            print("SAID mismatch:", vector["description"], actual)
+
+    print("failures:", failures)
+    sys.exit(1 if failures else 0)
+
+
+if __name__ == "__main__":
+    main()